@@ -1,18 +1,26 @@
-use anyhow::Result;
-
-use crate::{Directx, IBuffer, Queue};
+use crate::{Directx, IBuffer, MapRead, MapWrite, Queue, Result, SyncPoint};
 use std::ffi::c_void;
 
 pub struct BufferDirectx;
 
 impl IBuffer<Directx> for BufferDirectx {
     #[allow(unused)]
-    fn set<U>(&mut self, queue: &mut Queue<Directx>, source: &[U], buffer_offset: u64) -> Result<()>
+    fn set<U>(&mut self, queue: &Queue<Directx>, source: &[U], buffer_offset: u64) -> Result<SyncPoint<Directx>>
+        where U: Copy {
+        todo!()
+    }
+
+    #[allow(unused)]
+    fn upload<U>(&mut self, queue: &Queue<Directx>, source: &[U], buffer_offset: u64) -> Result<SyncPoint<Directx>>
         where U: Copy {
         todo!()
     }
 
-    fn host_address(&self) -> *mut c_void {
+    fn is_host_visible(&self) -> bool {
+        todo!()
+    }
+
+    fn host_address(&self) -> Result<*mut c_void> {
         todo!()
     }
 
@@ -20,11 +28,41 @@ impl IBuffer<Directx> for BufferDirectx {
         todo!()
     }
 
-    fn host_slice<U>(&self) -> &mut [U] {
+    fn host_slice<U>(&self) -> Result<&[U]>
+        where U: bytemuck::Pod {
         todo!()
     }
 
     fn size(&self) -> u64 {
         todo!()
     }
+
+    #[allow(unused)]
+    fn flush(&self, offset: u64, size: u64) -> Result<()> {
+        todo!()
+    }
+
+    #[allow(unused)]
+    fn invalidate(&self, offset: u64, size: u64) -> Result<()> {
+        todo!()
+    }
+
+    fn map_read<U>(&self) -> Result<MapRead<'_, U>>
+        where U: bytemuck::Pod {
+        todo!()
+    }
+
+    fn map_write<U>(&mut self) -> Result<MapWrite<'_, Directx, U>>
+        where U: bytemuck::Pod {
+        todo!()
+    }
+
+    #[allow(unused)]
+    fn set_name(&mut self, name: &str) {
+        todo!()
+    }
+
+    fn name(&self) -> Option<&str> {
+        todo!()
+    }
 }
\ No newline at end of file
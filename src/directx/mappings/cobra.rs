@@ -1,20 +1,25 @@
 use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::Result;
 use glam::UVec2;
 
-use crate::{Buffer, BufferFlags, Directx, ICobra, Image, ImageFormat, ImageUsage, Queue, QueueType, Sampler, Swapchain};
-use std::ffi::c_void;
+use crate::{AdapterInfo, Buffer, BufferDesc, CobraDesc, DeviceCapabilities, Directx, Fence, ICobra, Image, ImageFormat, ImageUsage, MemoryReport, Queue, QueueType, Result, Sampler, Swapchain, WaitMode};
+use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 
 pub struct CobraDirectx;
 
 impl ICobra<Directx> for CobraDirectx {
-    fn new() -> Result<Arc<Self>> {
+    #[allow(unused)]
+    fn new_with_desc(desc: CobraDesc) -> Result<Arc<Self>> {
+        todo!()
+    }
+
+    fn enumerate_adapters() -> Result<Vec<AdapterInfo>> {
         todo!()
     }
 
     #[allow(unused)]
-    fn new_buffer(&self, cobra: Arc<Self>, size: u64, flags: BufferFlags) -> Result<Buffer<Directx>> {
+    fn new_buffer_with_desc(&self, cobra: Arc<Self>, desc: BufferDesc) -> Result<Buffer<Directx>> {
         todo!()
     }
 
@@ -29,7 +34,17 @@ impl ICobra<Directx> for CobraDirectx {
     }
 
     #[allow(unused)]
-    fn new_swapchain(&self, cobra: Arc<Self>, window: *mut c_void, size: UVec2) -> Result<Swapchain<Directx>> {
+    fn new_swapchain(&self, cobra: Arc<Self>, window: &(impl HasWindowHandle + HasDisplayHandle), size: UVec2) -> Result<Swapchain<Directx>> {
+        todo!()
+    }
+
+    #[allow(unused)]
+    fn new_fence(&self, cobra: Arc<Self>) -> Result<Arc<Fence<Directx>>> {
+        todo!()
+    }
+
+    #[allow(unused)]
+    fn wait_for_fences(&self, fences: &[(&Fence<Directx>, u64)], mode: WaitMode, timeout: Duration) -> Result<bool> {
         todo!()
     }
 
@@ -38,7 +53,19 @@ impl ICobra<Directx> for CobraDirectx {
         todo!()
     }
 
+    fn adapter(&self) -> &AdapterInfo {
+        todo!()
+    }
+
+    fn capabilities(&self) -> &DeviceCapabilities {
+        todo!()
+    }
+
     fn supports_resizable_bar(&self) -> bool {
         todo!()
     }
+
+    fn memory_report(&self) -> Result<MemoryReport> {
+        todo!()
+    }
 }
\ No newline at end of file
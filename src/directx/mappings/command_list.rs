@@ -1,7 +1,6 @@
-use anyhow::Result;
-use glam::{IVec2, UVec2};
+use glam::{IVec2, UVec2, Vec4};
 
-use crate::{BlendFactor, BlendOp, Buffer, ClearValue, CompareOperation, Directx, ICommandList, Image, ImagePrimitive, IndexType, PipelineStage, Swapchain};
+use crate::{BlendFactor, BlendOp, Buffer, ClearValue, CompareOperation, Directx, ICommandList, Image, ImagePrimitive, IndexType, PipelineStage, QueueType, Result, Swapchain};
 
 pub struct CommandListDirectx;
 
@@ -68,7 +67,27 @@ impl ICommandList<Directx> for CommandListDirectx {
     }
 
     #[allow(unused)]
-    fn push_constant<U>(&self, value: &U) {
+    fn release_buffer(&self, buffer: &Buffer<Directx>, dst: QueueType) {
+        todo!()
+    }
+
+    #[allow(unused)]
+    fn acquire_buffer(&self, buffer: &Buffer<Directx>, src: QueueType) {
+        todo!()
+    }
+
+    #[allow(unused)]
+    fn release_image(&self, image: &Image<Directx>, dst: QueueType) {
+        todo!()
+    }
+
+    #[allow(unused)]
+    fn acquire_image(&self, image: &Image<Directx>, src: QueueType) {
+        todo!()
+    }
+
+    #[allow(unused)]
+    fn push_constant<U>(&self, value: &U) -> Result<()> {
         todo!()
     }
 
@@ -146,4 +165,18 @@ impl ICommandList<Directx> for CommandListDirectx {
     fn dispatch_indirect(&self, buffer: &Buffer<Directx>, offset: u64) {
         todo!()
     }
+
+    #[allow(unused)]
+    fn begin_label(&self, name: &str, color: Vec4) {
+        todo!()
+    }
+
+    fn end_label(&self) {
+        todo!()
+    }
+
+    #[allow(unused)]
+    fn insert_label(&self, name: &str, color: Vec4) {
+        todo!()
+    }
 }
\ No newline at end of file
@@ -1,6 +1,6 @@
-use anyhow::Result;
+use std::time::Duration;
 
-use crate::{Directx, IFence};
+use crate::{Directx, IFence, Result};
 
 pub struct FenceDirectx;
 
@@ -10,6 +10,16 @@ impl IFence<Directx> for FenceDirectx {
         todo!()
     }
 
+    #[allow(unused)]
+    fn wait_timeout(&self, value: u64, timeout: Duration) -> Result<bool> {
+        todo!()
+    }
+
+    #[allow(unused)]
+    fn signal(&self, value: u64) -> Result<()> {
+        todo!()
+    }
+
     fn pending_value(&self) -> u64 {
         todo!()
     }
@@ -1,13 +1,12 @@
-use anyhow::Result;
 use glam::UVec2;
 
-use crate::{Directx, IImage};
+use crate::{Directx, IImage, Queue, Result, SyncPoint};
 
 pub struct ImageDirectx;
 
 impl IImage<Directx> for ImageDirectx {
     #[allow(unused)]
-    fn set(&mut self, data: &[u8]) -> Result<()> {
+    fn set(&mut self, queue: &Queue<Directx>, data: &[u8]) -> Result<SyncPoint<Directx>> {
         todo!()
     }
 
@@ -18,4 +17,13 @@ impl IImage<Directx> for ImageDirectx {
     fn size(&self) -> UVec2 {
         todo!()
     }
+
+    #[allow(unused)]
+    fn set_name(&mut self, name: &str) {
+        todo!()
+    }
+
+    fn name(&self) -> Option<&str> {
+        todo!()
+    }
 }
\ No newline at end of file
@@ -11,9 +11,13 @@ pub use sampler::SamplerDirectx;
 pub mod command_list;
 pub mod queue;
 pub mod fence;
+pub mod upload_batch;
+pub mod readback;
 pub use command_list::CommandListDirectx;
 pub use queue::QueueDirectx;
 pub use fence::FenceDirectx;
+pub use upload_batch::UploadBatchDirectx;
+pub use readback::ReadbackDirectx;
 
 pub mod swapchain;
 pub use swapchain::SwapchainDirectx;
\ No newline at end of file
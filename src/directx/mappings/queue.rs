@@ -1,17 +1,15 @@
-use anyhow::Result;
-
-use crate::{CommandList, Directx, IQueue, Swapchain, SyncPoint};
+use crate::{Buffer, CommandList, Directx, IQueue, Image, Readback, Result, SubmitInfo, Swapchain, SyncPoint, UploadBatch};
 
 pub struct QueueDirectx;
 
 impl IQueue<Directx> for QueueDirectx {
     #[allow(unused)]
-    fn acquire(&self, swapchain: &mut Swapchain<Directx>) -> anyhow::Result<Option<SyncPoint<Directx>>> {
+    fn acquire(&self, swapchain: &mut Swapchain<Directx>) -> Result<Option<SyncPoint<Directx>>> {
         todo!()
     }
 
     #[allow(unused)]
-    fn present(&self, swapchain: &mut Swapchain<Directx>, wait: Option<&mut SyncPoint<Directx>>) -> Result<()> {
+    fn present(&self, swapchain: &mut Swapchain<Directx>, wait: Option<&SyncPoint<Directx>>) -> Result<()> {
         todo!()
     }
 
@@ -20,7 +18,26 @@ impl IQueue<Directx> for QueueDirectx {
     }
 
     #[allow(unused)]
-    fn submit(&self, cmd: CommandList<Directx>, wait: Option<&mut SyncPoint<Directx>>) -> Result<SyncPoint<Directx>> {
+    fn submit(&self, cmd: CommandList<Directx>, wait: Option<&SyncPoint<Directx>>) -> Result<SyncPoint<Directx>> {
+        todo!()
+    }
+
+    #[allow(unused)]
+    fn submit_batch(&self, info: SubmitInfo<'_, Directx>) -> Result<SyncPoint<Directx>> {
+        todo!()
+    }
+
+    fn begin_upload(&self) -> Result<UploadBatch<Directx>> {
+        todo!()
+    }
+
+    #[allow(unused)]
+    fn readback_buffer(&self, buffer: &Buffer<Directx>, offset: u64, size: u64, wait: Option<&SyncPoint<Directx>>) -> Result<Readback<Directx>> {
+        todo!()
+    }
+
+    #[allow(unused)]
+    fn readback_image(&self, image: &mut Image<Directx>, wait: Option<&SyncPoint<Directx>>) -> Result<Readback<Directx>> {
         todo!()
     }
 }
\ No newline at end of file
@@ -0,0 +1,21 @@
+use crate::{Directx, IReadback, Result, SyncPoint};
+
+pub struct ReadbackDirectx;
+
+impl IReadback<Directx> for ReadbackDirectx {
+    fn sync(&self) -> &SyncPoint<Directx> {
+        todo!()
+    }
+
+    fn size(&self) -> u64 {
+        todo!()
+    }
+
+    fn try_read(&self) -> Result<Option<Vec<u8>>> {
+        todo!()
+    }
+
+    fn read(self) -> Result<Vec<u8>> {
+        todo!()
+    }
+}
\ No newline at end of file
@@ -6,4 +6,13 @@ impl ISampler<Directx> for SamplerDirectx {
     fn handle(&self) -> u32 {
         todo!()
     }
+
+    #[allow(unused)]
+    fn set_name(&mut self, name: &str) {
+        todo!()
+    }
+
+    fn name(&self) -> Option<&str> {
+        todo!()
+    }
 }
\ No newline at end of file
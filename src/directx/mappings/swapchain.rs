@@ -17,4 +17,13 @@ impl ISwapchain<Directx> for SwapchainDirectx {
     fn size(&self) -> UVec2 {
         todo!()
     }
+
+    #[allow(unused)]
+    fn set_name(&mut self, name: &str) {
+        todo!()
+    }
+
+    fn name(&self) -> Option<&str> {
+        todo!()
+    }
 }
\ No newline at end of file
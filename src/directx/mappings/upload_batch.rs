@@ -0,0 +1,20 @@
+use crate::{Buffer, Directx, IUploadBatch, Image, Result, SyncPoint};
+
+pub struct UploadBatchDirectx;
+
+impl IUploadBatch<Directx> for UploadBatchDirectx {
+    #[allow(unused)]
+    fn write_buffer<U>(&mut self, buffer: &Buffer<Directx>, source: &[U], buffer_offset: u64) -> Result<()>
+        where U: Copy {
+        todo!()
+    }
+
+    #[allow(unused)]
+    fn write_image(&mut self, image: &Image<Directx>, data: &[u8]) -> Result<()> {
+        todo!()
+    }
+
+    fn submit(self) -> Result<SyncPoint<Directx>> {
+        todo!()
+    }
+}
\ No newline at end of file
@@ -0,0 +1,40 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CobraError {
+    OutOfDeviceMemory,
+    OutOfHostMemory,
+    DeviceLost,
+    SurfaceLost,
+    SwapchainOutOfDate,
+    Unsupported(String),
+    ShaderCompile(String),
+    InvalidUsage(String),
+    Backend(String) // any other failure reported by the underlying api
+}
+
+pub type Result<T> = std::result::Result<T, CobraError>;
+
+impl fmt::Display for CobraError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CobraError::OutOfDeviceMemory => write!(f, "Out of device memory"),
+            CobraError::OutOfHostMemory => write!(f, "Out of host memory"),
+            CobraError::DeviceLost => write!(f, "Device lost"),
+            CobraError::SurfaceLost => write!(f, "Surface lost"),
+            CobraError::SwapchainOutOfDate => write!(f, "Swapchain out of date"),
+            CobraError::Unsupported(message) => write!(f, "Unsupported: {}", message),
+            CobraError::ShaderCompile(message) => write!(f, "Shader compilation failed: {}", message),
+            CobraError::InvalidUsage(message) => write!(f, "Invalid usage: {}", message),
+            CobraError::Backend(message) => write!(f, "Backend error: {}", message)
+        }
+    }
+}
+
+impl std::error::Error for CobraError { }
+
+impl From<raw_window_handle::HandleError> for CobraError {
+    fn from(value: raw_window_handle::HandleError) -> Self {
+        CobraError::InvalidUsage(format!("Failed to get window handle: {}", value))
+    }
+}
@@ -0,0 +1,89 @@
+use crate::{CobraError, CobraType, CommandList, ICommandList, IQueue, Queue, Result, Swapchain, SyncPoint};
+
+struct FrameSlot<T, D>
+    where T: CobraType<T> {
+    sync: SyncPoint<T>,
+    data: D
+}
+
+// Keeps N frames in flight. A slot is only handed out again once the gpu has finished the frame that last used it,
+// so anything stored in the slot data (uniform rings, descriptors, per frame buffers) can be reused freely
+pub struct FrameRing<T, D>
+    where T: CobraType<T> {
+    slots: Vec<FrameSlot<T, D>>,
+    slot_index: usize,
+    frame_number: u64
+}
+
+impl<T, D> FrameRing<T, D>
+    where T: CobraType<T> {
+    pub fn new(frames_in_flight: usize, mut init: impl FnMut(usize) -> D) -> Result<FrameRing<T, D>> {
+        if frames_in_flight == 0 {
+            return Err(CobraError::InvalidUsage("A frame ring needs at least one frame in flight".to_owned()));
+        }
+
+        Ok(FrameRing {
+            slots: (0..frames_in_flight).map(|i| FrameSlot { sync: SyncPoint::new(), data: init(i) }).collect(),
+            slot_index: 0,
+            frame_number: 0
+        })
+    }
+
+    // Blocks until the gpu is done with the current slot's previous frame
+    pub fn begin_frame(&mut self) -> Result<&mut D> {
+        let slot = &mut self.slots[self.slot_index];
+        slot.sync.wait()?;
+
+        Ok(&mut slot.data)
+    }
+
+    // The sync point should cover all of the work that uses the slot's data
+    pub fn end_frame(&mut self, sync: SyncPoint<T>) {
+        self.slots[self.slot_index].sync = sync;
+        self.slot_index = (self.slot_index + 1) % self.slots.len();
+        self.frame_number += 1;
+    }
+
+    // Acquires, records, submits and presents a frame. Returns false without advancing when the swapchain was out of date,
+    // the swapchain is recreated on the next acquire so the caller can simply try again next frame
+    pub fn present_frame(&mut self, queue: &Queue<T>, swapchain: &mut Swapchain<T>, record: impl FnOnce(&mut CommandList<T>, &mut Swapchain<T>, &mut D) -> Result<()>) -> Result<bool> {
+        self.begin_frame()?;
+
+        let Some(acquire_sync) = queue.acquire(swapchain)? else { return Ok(false); };
+
+        let mut cmd = queue.begin()?;
+        record(&mut cmd, swapchain, &mut self.slots[self.slot_index].data)?;
+        cmd.present(swapchain);
+
+        let sync = queue.submit(cmd, Some(&acquire_sync))?;
+        queue.present(swapchain, Some(&sync))?;
+
+        self.end_frame(sync);
+        Ok(true)
+    }
+
+    // Waits for every frame in flight, call before destroying or resizing anything stored in the slots
+    pub fn wait_idle(&self) -> Result<()> {
+        SyncPoint::join(self.slots.iter().map(|slot| slot.sync.clone())).wait()
+    }
+
+    pub fn current(&mut self) -> &mut D {
+        &mut self.slots[self.slot_index].data
+    }
+
+    pub fn slots_mut(&mut self) -> impl Iterator<Item = &mut D> {
+        self.slots.iter_mut().map(|slot| &mut slot.data)
+    }
+
+    pub fn frames_in_flight(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn slot_index(&self) -> usize {
+        self.slot_index
+    }
+
+    pub fn frame_number(&self) -> u64 {
+        self.frame_number
+    }
+}
@@ -0,0 +1,256 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::{slang::Ptr, Buffer, BufferFlags, Cobra, CobraError, CobraType, IBuffer, ICobra, ICommandList, IQueue, IUploadBatch, PipelineStage, Queue, Result, SyncPoint};
+
+const MIN_CAPACITY: usize = 16;
+// The header stores len and capacity as u32 for shaders
+const MAX_CAPACITY: usize = u32::MAX as usize;
+
+// What the header buffer holds, shaders go through this so they always see the current data buffer
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuVecHeader {
+    pub address: u64,
+    pub len: u32,
+    pub capacity: u32
+}
+
+// A growable array in device memory. Changes are made to a host copy and sent over with upload, only the elements that
+// changed are written. Growing allocates a new buffer and copies the old contents on the gpu, while the header buffer
+// keeps its address for the whole lifetime so shaders can hold on to it
+pub struct GpuVec<T, U>
+    where T: CobraType<T>, U: bytemuck::Pod {
+    cobra: Arc<Cobra<T>>,
+    header: Buffer<T>,
+    buffer: Option<Buffer<T>>,
+    capacity: usize,
+
+    items: Vec<U>,
+    dirty: Option<Range<usize>>,
+    header_dirty: bool,
+
+    gpu_len: usize, // elements in the buffer as of the last upload
+    pending_copy: Option<Buffer<T>> // previous buffer, copied over on the next upload
+}
+
+impl<T, U> GpuVec<T, U>
+    where T: CobraType<T>, U: bytemuck::Pod {
+    pub fn new(cobra: Arc<Cobra<T>>) -> Result<GpuVec<T, U>> {
+        Self::with_capacity(cobra, 0)
+    }
+
+    pub fn with_capacity(cobra: Arc<Cobra<T>>, capacity: usize) -> Result<GpuVec<T, U>> {
+        let header = cobra.new_buffer(cobra.clone(), std::mem::size_of::<GpuVecHeader>() as u64, BufferFlags::Default)?;
+        let mut ret = GpuVec {
+            cobra, header,
+            buffer: None,
+            capacity: 0,
+
+            items: Vec::with_capacity(capacity),
+            dirty: None,
+            header_dirty: true,
+
+            gpu_len: 0,
+            pending_copy: None
+        };
+
+        if capacity > 0 { ret.grow(capacity)?; }
+        Ok(ret)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn as_slice(&self) -> &[U] {
+        &self.items
+    }
+
+    pub fn get(&self, index: usize) -> Option<&U> {
+        self.items.get(index)
+    }
+
+    pub fn push(&mut self, value: U) -> Result<()> {
+        self.reserve(1)?;
+        self.items.push(value);
+        self.mark_dirty(self.items.len() - 1..self.items.len());
+        self.header_dirty = true;
+
+        Ok(())
+    }
+
+    pub fn extend(&mut self, values: impl IntoIterator<Item = U>) -> Result<()> {
+        let values = values.into_iter();
+
+        self.reserve(values.size_hint().0)?;
+        for value in values {
+            self.push(value)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn set(&mut self, index: usize, value: U) {
+        self.items[index] = value;
+        self.mark_dirty(index..index + 1);
+    }
+
+    // Moves the last element into the hole like Vec::swap_remove, panics if the index is out of bounds
+    pub fn remove_swap(&mut self, index: usize) -> U {
+        let value = self.items.swap_remove(index);
+        if index < self.items.len() {
+            self.mark_dirty(index..index + 1);
+        }
+        self.header_dirty = true;
+
+        value
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.dirty = None;
+        self.header_dirty = true;
+    }
+
+    pub fn reserve(&mut self, additional: usize) -> Result<()> {
+        let required = self.items.len().saturating_add(additional);
+        if required <= self.capacity { return Ok(()); }
+
+        // Doubling stops at the limit, only asking for more than it fails
+        self.grow(usize::max(required, usize::min(usize::max(self.capacity * 2, MIN_CAPACITY), MAX_CAPACITY)))
+    }
+
+    // Sends every change since the last upload, including the copy out of the old buffer after growing
+    pub fn upload(&mut self, queue: &Queue<T>) -> Result<SyncPoint<T>> {
+        let mut sync = SyncPoint::new();
+        let dirty = self.dirty.take().map(|dirty| dirty.start..usize::min(dirty.end, self.items.len()));
+
+        if let Some(old) = self.pending_copy.take() {
+            let copy_len = usize::min(self.gpu_len, self.items.len());
+            let element_size = std::mem::size_of::<U>() as u64;
+
+            let regions = copy_regions(copy_len, dirty.as_ref());
+            if regions.iter().any(|region| !region.is_empty()) {
+                // Submission order alone doesn't order execution, the last upload into the old buffer has to land first
+                let cmd = queue.begin()?;
+                cmd.barrier(PipelineStage::All, PipelineStage::Transfer);
+                for region in regions.iter().filter(|region| !region.is_empty()) {
+                    let offset = region.start as u64 * element_size;
+                    cmd.copy_buffer_region(&old, self.buffer.as_ref().unwrap(), region.len() as u64 * element_size, offset, offset);
+                }
+
+                sync.merge(&queue.submit(cmd, None)?);
+            }
+            // Dropped after the submission, so it's only freed once the copy is done
+        }
+
+        let mut upload = queue.begin_upload()?;
+        if let Some(dirty) = dirty.filter(|dirty| !dirty.is_empty()) {
+            upload.write_buffer(self.buffer.as_ref().unwrap(), &self.items[dirty.clone()], (dirty.start * std::mem::size_of::<U>()) as u64)?;
+        }
+        if self.header_dirty {
+            upload.write_buffer(&self.header, &[self.header_value()], 0)?;
+            self.header_dirty = false;
+        }
+        sync.merge(&upload.submit()?);
+
+        self.gpu_len = self.items.len();
+        Ok(sync)
+    }
+
+    // Stays the same for the lifetime of the vector
+    pub fn header_address(&self) -> u64 {
+        self.header.device_address()
+    }
+
+    pub fn header_ptr(&self) -> Ptr<GpuVecHeader> {
+        Ptr::from_address(self.header_address())
+    }
+
+    // Changes whenever the vector grows, prefer going through the header in shaders
+    pub fn device_address(&self) -> u64 {
+        self.buffer.as_ref().map_or(0, |buffer| buffer.device_address())
+    }
+
+    fn header_value(&self) -> GpuVecHeader {
+        GpuVecHeader {
+            address: self.device_address(),
+            // grow keeps the capacity within u32, and the length within the capacity
+            len: self.items.len() as u32,
+            capacity: self.capacity as u32
+        }
+    }
+
+    fn mark_dirty(&mut self, range: Range<usize>) {
+        self.dirty = Some(merge_dirty(self.dirty.take(), range));
+    }
+
+    fn grow(&mut self, capacity: usize) -> Result<()> {
+        let size = match capacity <= MAX_CAPACITY {
+            true => (capacity as u64).checked_mul(std::mem::size_of::<U>() as u64),
+            false => None
+        };
+        let Some(size) = size else {
+            return Err(CobraError::InvalidUsage(format!("A capacity of {} elements is more than a gpu vector can hold, the limit is {}", capacity, MAX_CAPACITY)));
+        };
+
+        let buffer = self.cobra.new_buffer(self.cobra.clone(), size, BufferFlags::Default)?;
+
+        // When growing twice between uploads the buffer in the middle never got any data, only the first one has to be copied from
+        let old = self.buffer.replace(buffer);
+        if self.pending_copy.is_none() && self.gpu_len > 0 {
+            self.pending_copy = old;
+        }
+
+        self.capacity = capacity;
+        self.header_dirty = true;
+        Ok(())
+    }
+}
+
+fn merge_dirty(dirty: Option<Range<usize>>, range: Range<usize>) -> Range<usize> {
+    match dirty {
+        Some(dirty) => usize::min(dirty.start, range.start)..usize::max(dirty.end, range.end),
+        None => range
+    }
+}
+
+// Parts of the first copy_len elements to copy out of the old buffer after growing. The dirty elements are uploaded anyway,
+// skipping them keeps the copy and the upload from writing the same memory
+fn copy_regions(copy_len: usize, dirty: Option<&Range<usize>>) -> [Range<usize>; 2] {
+    let (skip_start, skip_end) = dirty.map_or((copy_len, copy_len), |dirty| (dirty.start, dirty.end));
+    [0..usize::min(skip_start, copy_len), usize::min(skip_end, copy_len)..copy_len]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dirty_ranges_merge() {
+        assert_eq!(merge_dirty(None, 3..4), 3..4);
+        assert_eq!(merge_dirty(Some(3..4), 1..2), 1..4);
+        assert_eq!(merge_dirty(Some(1..4), 2..3), 1..4);
+        assert_eq!(merge_dirty(Some(1..2), 6..8), 1..8);
+    }
+
+    #[test]
+    fn grow_copy_skips_dirty_elements() {
+        assert_eq!(copy_regions(8, None), [0..8, 8..8]);
+        assert_eq!(copy_regions(8, Some(&(2..4))), [0..2, 4..8]);
+        assert_eq!(copy_regions(8, Some(&(0..8))), [0..0, 8..8]);
+        // Only pushed elements are dirty, nothing past the old length has to be copied
+        assert_eq!(copy_regions(8, Some(&(6..12))), [0..6, 8..8]);
+        assert_eq!(copy_regions(8, Some(&(8..12))), [0..8, 8..8]);
+        assert_eq!(copy_regions(0, Some(&(0..4))), [0..0, 0..0]);
+    }
+}
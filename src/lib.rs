@@ -1,13 +1,22 @@
+mod error;
 pub mod slang;
 pub mod traits;
 pub use traits::*;
+pub mod frame_ring;
+pub use frame_ring::*;
+pub mod transient_allocator;
+pub use transient_allocator::*;
+pub mod typed_buffer;
+pub use typed_buffer::*;
+pub mod gpu_vec;
+pub use gpu_vec::*;
 
 #[cfg(feature="vulkan")]
 pub mod vulkan;
 #[cfg(feature="directx")]
 pub mod directx;
 
-pub use anyhow::{Result, Error};
+pub use error::{CobraError, Result};
 pub use glam::*;
 
 use paste::paste;
@@ -37,7 +46,7 @@ macro_rules! create_primitive {
 pub trait CobraType<T>: CobraPrimitive<T> + 
     BufferPrimitive<T> + ImagePrimitive<T> + SamplerPrimitive<T> +
     CommandListPrimitive<T> + QueuePrimitive<T> + FencePrimitive<T> + 
-    SwapchainPrimitive<T>
+    UploadBatchPrimitive<T> + ReadbackPrimitive<T> + SwapchainPrimitive<T>
     where T: CobraType<T> { }
 #[cfg(feature="vulkan")]
 impl CobraType<Vulkan> for Vulkan { }
@@ -53,5 +62,7 @@ create_primitive!(Sampler);
 create_primitive!(CommandList);
 create_primitive!(Queue);
 create_primitive!(Fence);
+create_primitive!(UploadBatch);
+create_primitive!(Readback);
 
 create_primitive!(Swapchain);
\ No newline at end of file
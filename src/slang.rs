@@ -1,11 +1,94 @@
-use crate::{CobraType, IImage, ISampler, Image, Sampler};
+use crate::{Buffer, CobraError, CobraType, IBuffer, IImage, ISampler, Image, Result, Sampler, TypedBuffer, TypedBufferSlice};
 
+// Device address of a T, matches a T* in slang. Arithmetic steps in whole elements like host pointers do
 #[repr(transparent)]
 #[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Ptr<T> {
+    address: u64,
     _phantom: std::marker::PhantomData<T>
 }
 
+impl<T> Ptr<T> {
+    pub fn null() -> Ptr<T> {
+        Self::from_address(0)
+    }
+
+    pub fn from_address(address: u64) -> Ptr<T> {
+        Ptr {
+            address,
+            _phantom: std::marker::PhantomData
+        }
+    }
+
+    // The offset may point one past the end, like an end pointer on the host
+    pub fn from_buffer<C>(buffer: &Buffer<C>, offset: u64) -> Result<Ptr<T>>
+        where C: CobraType<C> {
+        if offset > buffer.size() {
+            return Err(CobraError::InvalidUsage(format!("Offset {} is out of range for a buffer of {} bytes", offset, buffer.size())));
+        }
+
+        Ok(Self::from_address(buffer.device_address() + offset))
+    }
+
+    pub fn from_typed_buffer<C>(buffer: &TypedBuffer<C, T>, index: usize) -> Result<Ptr<T>>
+        where C: CobraType<C>, T: bytemuck::Pod {
+        Ok(Self::from_address(buffer.device_address_of(index)?))
+    }
+
+    pub fn from_typed_slice<C>(slice: &TypedBufferSlice<'_, C, T>) -> Ptr<T>
+        where C: CobraType<C>, T: bytemuck::Pod {
+        Self::from_address(slice.device_address())
+    }
+
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.address == 0
+    }
+
+    // Arithmetic wraps around like the pointer math in shaders does, in debug builds too. Named after the host pointer
+    // methods rather than implementing the operators, since the count is in elements
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(self, count: u64) -> Ptr<T> {
+        Self::from_address(self.address.wrapping_add(count.wrapping_mul(std::mem::size_of::<T>() as u64)))
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn sub(self, count: u64) -> Ptr<T> {
+        Self::from_address(self.address.wrapping_sub(count.wrapping_mul(std::mem::size_of::<T>() as u64)))
+    }
+
+    pub fn byte_add(self, bytes: u64) -> Ptr<T> {
+        Self::from_address(self.address.wrapping_add(bytes))
+    }
+
+    pub fn cast<U>(self) -> Ptr<U> {
+        Ptr::from_address(self.address)
+    }
+}
+
+impl<T> std::fmt::Debug for Ptr<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Ptr({:#x})", self.address)
+    }
+}
+
+impl<T> PartialEq for Ptr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.address == other.address
+    }
+}
+
+impl<T> Eq for Ptr<T> { }
+
+impl<T> From<Ptr<T>> for u64 {
+    fn from(value: Ptr<T>) -> Self {
+        value.address
+    }
+}
+
 #[repr(transparent)]
 #[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct ImageHandle<T> {
@@ -59,4 +142,26 @@ macro_rules! slang_include {
     {
         slang_struct!($($lisp_source)*);
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pointer_arithmetic() {
+        let ptr = Ptr::<u32>::from_address(0x1000);
+        assert_eq!(ptr.add(4).address(), 0x1010);
+        assert_eq!(ptr.sub(4).address(), 0xff0);
+        assert_eq!(ptr.byte_add(2).address(), 0x1002);
+        assert_eq!(ptr.cast::<u64>().add(1).address(), 0x1008);
+        assert_eq!(ptr.add(4).sub(4), ptr);
+
+        // Wraps instead of overflowing, in debug builds too
+        assert_eq!(Ptr::<u32>::null().sub(1).address(), u64::MAX - 3);
+        assert_eq!(Ptr::<u32>::from_address(u64::MAX).byte_add(1), Ptr::null());
+        assert!(Ptr::<u32>::from_address(4).sub(1).is_null());
+
+        assert_eq!(u64::from(ptr), 0x1000);
+        assert_eq!(format!("{:?}", ptr), "Ptr(0x1000)");
+    }
+}
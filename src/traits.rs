@@ -1,20 +1,112 @@
-use std::{ffi::c_void, sync::Arc};
+use std::{collections::HashMap, ffi::c_void, marker::PhantomData, ops::{Deref, DerefMut}, sync::Arc, time::{Duration, Instant}};
+
+use raw_window_handle::{HasDisplayHandle, HasWindowHandle, RawDisplayHandle};
 
-use anyhow::Result;
 use glam::{IVec2, IVec4, UVec2, UVec4, Vec4};
 
-use crate::{Buffer, CobraType, CommandList, Fence, Image, ImagePrimitive, Queue, Sampler, Swapchain};
+use crate::{Buffer, CobraError, CobraType, CommandList, Fence, Image, ImagePrimitive, Queue, Readback, Result, Sampler, Swapchain, UploadBatch};
 
 // Buffer info
+#[derive(Hash, Eq, PartialEq, Clone, Copy, Debug)]
 pub enum BufferFlags {
     Default, // device_local
     Upload, // host_local | host_visible | host_coherent (pref host_cached)
     Readback, // host_local | host_visible | host_coherent | host_cached
-    DeviceUpload // device_local | host_visible | host_coherent
+    DeviceUpload // device_local, and host_visible | host_coherent with resizable bar. Write with IBuffer::upload
+}
+
+bitflags::bitflags! {
+    #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+    pub struct BufferUsage: u32 {
+        const TransferSrc = 1;
+        const TransferDst = 2;
+        const Storage = 4;
+        const Index = 8;
+        const Indirect = 16;
+        const Vertex = 32;
+        const Uniform = 64;
+        const AccelerationStructureInput = 128; // needs DeviceFeatures::RayTracing
+        const ShaderBindingTable = 256; // needs DeviceFeatures::RayTracing
+        const Default = BufferUsage::TransferSrc.union(BufferUsage::TransferDst).union(BufferUsage::Storage)
+            .union(BufferUsage::Index).union(BufferUsage::Indirect).bits();
+    }
+}
+
+#[derive(Hash, Eq, PartialEq, Clone, Copy, Debug)]
+pub enum MemoryPlacement {
+    GpuOnly, // device_local, not mappable
+    CpuToGpu, // host_visible | host_coherent, written sequentially by the host
+    GpuToCpu, // host_visible, prefers host_cached for fast reads so it might not be coherent. Read through IBuffer::map_read
+    // The two resizable bar placements differ in what they give up without it. PreferRebar is always mappable and moves to
+    // host memory, DeviceUpload always stays device_local and loses mapping
+    PreferRebar, // host_visible | host_coherent, device_local when possible, otherwise the same as CpuToGpu
+    DeviceUpload // device_local, also host_visible with resizable bar. Not always mappable, meant to be written with IBuffer::upload
+}
+
+impl From<BufferFlags> for MemoryPlacement {
+    fn from(value: BufferFlags) -> Self {
+        match value {
+            BufferFlags::Default => MemoryPlacement::GpuOnly,
+            BufferFlags::Upload => MemoryPlacement::CpuToGpu,
+            BufferFlags::Readback => MemoryPlacement::GpuToCpu,
+            BufferFlags::DeviceUpload => MemoryPlacement::DeviceUpload
+        }
+    }
+}
+
+impl From<MemoryPlacement> for BufferFlags {
+    fn from(value: MemoryPlacement) -> Self {
+        match value {
+            MemoryPlacement::GpuOnly => BufferFlags::Default,
+            MemoryPlacement::CpuToGpu => BufferFlags::Upload,
+            MemoryPlacement::GpuToCpu => BufferFlags::Readback,
+            // Always mappable and written by the host, so it's reported along with the other upload buffers
+            MemoryPlacement::PreferRebar => BufferFlags::Upload,
+            MemoryPlacement::DeviceUpload => BufferFlags::DeviceUpload
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BufferDesc {
+    pub size: u64,
+    pub usage: BufferUsage,
+    pub placement: MemoryPlacement,
+    pub dedicated: bool // gives the buffer its own memory block instead of suballocating, for large long lived buffers
+}
+
+impl BufferDesc {
+    pub fn new(size: u64) -> Self {
+        BufferDesc {
+            size,
+            usage: BufferUsage::Default,
+            placement: MemoryPlacement::GpuOnly,
+            dedicated: false
+        }
+    }
+
+    pub fn from_flags(size: u64, flags: BufferFlags) -> Self {
+        BufferDesc::new(size).placement(flags.into())
+    }
+
+    pub fn usage(mut self, usage: BufferUsage) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    pub fn placement(mut self, placement: MemoryPlacement) -> Self {
+        self.placement = placement;
+        self
+    }
+
+    pub fn dedicated(mut self, dedicated: bool) -> Self {
+        self.dedicated = dedicated;
+        self
+    }
 }
 
 // Image info
-#[derive(Hash, Eq, PartialEq, Clone, Copy)]
+#[derive(Hash, Eq, PartialEq, Clone, Copy, Debug)]
 pub enum ImageFormat {
    Unknown,
    R32Sint,
@@ -25,8 +117,19 @@ pub enum ImageFormat {
    D32SFloat
 }
 
+impl ImageFormat {
+   // Bytes per texel in tightly packed host memory
+   pub fn texel_size(&self) -> u64 {
+      match self {
+         ImageFormat::Unknown => 0,
+         ImageFormat::R32Sint | ImageFormat::R8G8B8A8Unorm | ImageFormat::B8G8R8A8Srgb | ImageFormat::D32SFloat => 4,
+         ImageFormat::R16G16B16A16Sfloat | ImageFormat::R16G16B16A16Unorm => 8
+      }
+   }
+}
+
 bitflags::bitflags! {
-   #[derive(Clone, Copy, PartialEq, Eq)]
+   #[derive(Clone, Copy, PartialEq, Eq, Debug)]
     pub struct ImageUsage: u32 {
       const None = 0;
       const ColorAttachment = 1;
@@ -110,8 +213,365 @@ pub enum BlendOp {
 }
 
 // Queue info
+#[derive(Hash, Eq, PartialEq, Clone, Copy, Debug)]
 pub enum QueueType {
-    Graphics
+    Graphics,
+    Compute, // falls back to the graphics queue when the device has no dedicated compute family
+    Transfer // falls back to the compute queue, then the graphics queue
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WaitMode {
+    Any,
+    All
+}
+
+// Adapter info
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AdapterType {
+    Other,
+    IntegratedGpu,
+    DiscreteGpu,
+    VirtualGpu,
+    Cpu
+}
+
+#[derive(Clone, Debug)]
+pub struct MemoryHeapInfo {
+    pub size: u64,
+    pub device_local: bool
+}
+
+#[derive(Clone, Debug)]
+pub struct AdapterInfo {
+    pub index: usize,
+    pub name: String,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub ty: AdapterType,
+    pub driver_version: u32,
+    pub memory_heaps: Vec<MemoryHeapInfo>
+}
+
+#[derive(Clone, Debug, Default)]
+pub enum AdapterSelection {
+    #[default]
+    Default, // first discrete gpu, otherwise the first adapter
+    Index(usize),
+    Name(String), // case insensitive substring of the adapter name
+    Type(AdapterType)
+}
+
+impl AdapterSelection {
+    pub fn select(&self, adapters: &[AdapterInfo]) -> Option<usize> {
+        match self {
+            AdapterSelection::Default => adapters.iter()
+                .position(|adapter| adapter.ty == AdapterType::DiscreteGpu)
+                .or(if adapters.is_empty() { None } else { Some(0) }),
+            AdapterSelection::Index(index) => adapters.iter().position(|adapter| adapter.index == *index),
+            AdapterSelection::Name(name) => {
+                let name = name.to_lowercase();
+                adapters.iter().position(|adapter| adapter.name.to_lowercase().contains(&name))
+            },
+            AdapterSelection::Type(ty) => adapters.iter().position(|adapter| adapter.ty == *ty)
+        }
+    }
+}
+
+// Device capabilities
+bitflags::bitflags! {
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub struct DeviceFeatures: u32 {
+        const ResizableBar = 1;
+        const MemoryBudget = 2;
+        const ShaderInt64 = 4;
+        const ShaderInt16 = 8;
+        const ShaderInt8 = 16;
+        const ShaderFloat64 = 32;
+        const ShaderFloat16 = 64;
+        const MultiDrawIndirect = 128;
+        const DrawIndirectCount = 256;
+        const SamplerAnisotropy = 512;
+        const MeshShader = 1024;
+        const RayTracing = 2048;
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DeviceLimits {
+    pub max_image_dimension_2d: u32,
+    pub max_push_constant_size: u32,
+
+    pub max_bindless_samplers: u32,
+    pub max_bindless_sampled_images: u32,
+    pub max_bindless_storage_images: u32,
+
+    pub max_compute_workgroup_count: [u32; 3],
+    pub max_compute_workgroup_size: [u32; 3],
+    pub max_compute_workgroup_invocations: u32,
+
+    pub subgroup_size: u32,
+    pub min_subgroup_size: u32,
+    pub max_subgroup_size: u32
+}
+
+#[derive(Clone, Debug)]
+pub struct DeviceCapabilities {
+    pub limits: DeviceLimits,
+    pub features: DeviceFeatures,
+    pub format_usages: HashMap<ImageFormat, ImageUsage>
+}
+
+impl DeviceCapabilities {
+    pub fn supports(&self, features: DeviceFeatures) -> bool {
+        self.features.contains(features)
+    }
+
+    pub fn format_usage(&self, format: ImageFormat) -> ImageUsage {
+        self.format_usages.get(&format).copied().unwrap_or(ImageUsage::None)
+    }
+
+    pub fn supports_format(&self, format: ImageFormat, usage: ImageUsage) -> bool {
+        self.format_usage(format).contains(usage)
+    }
+}
+
+// Memory info
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AllocationStats {
+    pub count: u64,
+    pub bytes: u64
+}
+
+#[derive(Clone, Debug)]
+pub struct MemoryHeapReport {
+    pub size: u64,
+    pub device_local: bool,
+
+    // Without memory budget support these are estimates made from the allocator's own allocations
+    pub usage: u64,
+    pub budget: u64,
+
+    pub block_count: u64,
+    pub block_bytes: u64,
+    pub allocations: AllocationStats
+}
+
+#[derive(Clone, Debug)]
+pub struct MemoryReport {
+    pub heaps: Vec<MemoryHeapReport>,
+    pub buffers: HashMap<BufferFlags, AllocationStats>,
+    pub images: AllocationStats
+}
+
+impl MemoryReport {
+    pub fn usage(&self) -> u64 {
+        self.heaps.iter().map(|heap| heap.usage).sum()
+    }
+
+    pub fn budget(&self) -> u64 {
+        self.heaps.iter().map(|heap| heap.budget).sum()
+    }
+
+    pub fn device_local_usage(&self) -> u64 {
+        self.heaps.iter().filter(|heap| heap.device_local).map(|heap| heap.usage).sum()
+    }
+
+    pub fn device_local_budget(&self) -> u64 {
+        self.heaps.iter().filter(|heap| heap.device_local).map(|heap| heap.budget).sum()
+    }
+
+    pub fn buffer_stats(&self, flags: BufferFlags) -> AllocationStats {
+        self.buffers.get(&flags).copied().unwrap_or_default()
+    }
+
+    pub fn to_json(&self) -> String {
+        let stats_json = |stats: AllocationStats| format!("{{\"count\":{},\"bytes\":{}}}", stats.count, stats.bytes);
+
+        let heaps: Vec<String> = self.heaps.iter().map(|heap| format!(
+            "{{\"size\":{},\"device_local\":{},\"usage\":{},\"budget\":{},\"block_count\":{},\"block_bytes\":{},\"allocations\":{}}}",
+            heap.size, heap.device_local, heap.usage, heap.budget, heap.block_count, heap.block_bytes, stats_json(heap.allocations)
+        )).collect();
+
+        let buffers: Vec<String> = [BufferFlags::Default, BufferFlags::Upload, BufferFlags::Readback, BufferFlags::DeviceUpload]
+            .into_iter()
+            .map(|flags| format!("\"{:?}\":{}", flags, stats_json(self.buffer_stats(flags))))
+            .collect();
+
+        format!("{{\"heaps\":[{}],\"buffers\":{{{}}},\"images\":{}}}", heaps.join(","), buffers.join(","), stats_json(self.images))
+    }
+}
+
+// Debug info
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum DebugSeverity {
+    Verbose,
+    Info,
+    Warning,
+    Error
+}
+
+pub type DebugCallback = Arc<dyn Fn(DebugSeverity, &str) + Send + Sync>;
+
+#[derive(Clone)]
+pub struct DebugDesc {
+    pub validation: bool,
+    pub min_severity: DebugSeverity,
+    pub callback: Option<DebugCallback> // forwarded to the log crate when none is set
+}
+
+impl Default for DebugDesc {
+    fn default() -> Self {
+        DebugDesc {
+            validation: true,
+            min_severity: DebugSeverity::Warning,
+            callback: None
+        }
+    }
+}
+
+impl DebugDesc {
+    pub fn validation(mut self, validation: bool) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    pub fn min_severity(mut self, min_severity: DebugSeverity) -> Self {
+        self.min_severity = min_severity;
+        self
+    }
+
+    pub fn callback(mut self, callback: impl Fn(DebugSeverity, &str) + Send + Sync + 'static) -> Self {
+        self.callback = Some(Arc::new(callback));
+        self
+    }
+}
+
+// Context info
+// Image and sampler ids share a single 32 bit handle in shaders (see slang::ImageHandle), which caps the bindless table sizes
+pub const MAX_BINDLESS_IMAGES: u32 = 1 << 20;
+pub const MAX_BINDLESS_SAMPLERS: u32 = 1 << 12;
+
+#[derive(Clone)]
+pub struct CobraDesc {
+    pub adapter: AdapterSelection,
+    pub debug: Option<DebugDesc>,
+    pub headless: bool, // skips surface and swapchain support entirely
+    pub display: Option<RawDisplayHandle>, // only enables the surface extension for this display, otherwise every available one
+
+    pub api_version: (u32, u32),
+    pub features: DeviceFeatures, // optional features that must be supported and get enabled
+    pub bindless_images: u32,
+    pub bindless_samplers: u32,
+    pub push_constant_size: u32,
+    pub staging_buffer_size: u64
+}
+
+impl Default for CobraDesc {
+    fn default() -> Self {
+        CobraDesc {
+            adapter: AdapterSelection::Default,
+            debug: None,
+            headless: false,
+            display: None,
+
+            api_version: (1, 3),
+            features: DeviceFeatures::empty(),
+            bindless_images: MAX_BINDLESS_IMAGES,
+            bindless_samplers: MAX_BINDLESS_SAMPLERS,
+            push_constant_size: 128,
+            staging_buffer_size: 64 * 1024 * 1024
+        }
+    }
+}
+
+impl CobraDesc {
+    pub fn adapter(mut self, adapter: AdapterSelection) -> Self {
+        self.adapter = adapter;
+        self
+    }
+
+    pub fn debug(mut self, debug: DebugDesc) -> Self {
+        self.debug = Some(debug);
+        self
+    }
+
+    pub fn headless(mut self, headless: bool) -> Self {
+        self.headless = headless;
+        self
+    }
+
+    pub fn display(mut self, display: RawDisplayHandle) -> Self {
+        self.display = Some(display);
+        self
+    }
+
+    pub fn api_version(mut self, major: u32, minor: u32) -> Self {
+        self.api_version = (major, minor);
+        self
+    }
+
+    pub fn features(mut self, features: DeviceFeatures) -> Self {
+        self.features = features;
+        self
+    }
+
+    pub fn bindless_images(mut self, count: u32) -> Self {
+        self.bindless_images = count;
+        self
+    }
+
+    pub fn bindless_samplers(mut self, count: u32) -> Self {
+        self.bindless_samplers = count;
+        self
+    }
+
+    pub fn push_constant_size(mut self, size: u32) -> Self {
+        self.push_constant_size = size;
+        self
+    }
+
+    pub fn staging_buffer_size(mut self, size: u64) -> Self {
+        self.staging_buffer_size = size;
+        self
+    }
+
+    pub fn validate(&self, capabilities: &DeviceCapabilities) -> Result<()> {
+        let limits = &capabilities.limits;
+
+        if self.bindless_images == 0 || self.bindless_images > MAX_BINDLESS_IMAGES {
+            return Err(CobraError::InvalidUsage(format!("bindless_images must be between 1 and {}, got {}", MAX_BINDLESS_IMAGES, self.bindless_images)));
+        }
+        if self.bindless_samplers == 0 || self.bindless_samplers > MAX_BINDLESS_SAMPLERS {
+            return Err(CobraError::InvalidUsage(format!("bindless_samplers must be between 1 and {}, got {}", MAX_BINDLESS_SAMPLERS, self.bindless_samplers)));
+        }
+
+        let max_images = u32::min(limits.max_bindless_sampled_images, limits.max_bindless_storage_images);
+        if self.bindless_images > max_images {
+            return Err(CobraError::Unsupported(format!("Requested {} bindless images but the device supports at most {}", self.bindless_images, max_images)));
+        }
+        if self.bindless_samplers > limits.max_bindless_samplers {
+            return Err(CobraError::Unsupported(format!("Requested {} bindless samplers but the device supports at most {}", self.bindless_samplers, limits.max_bindless_samplers)));
+        }
+
+        if !self.push_constant_size.is_multiple_of(4) {
+            return Err(CobraError::InvalidUsage(format!("push_constant_size must be a multiple of 4, got {}", self.push_constant_size)));
+        }
+        if self.push_constant_size > limits.max_push_constant_size {
+            return Err(CobraError::Unsupported(format!("Requested {} bytes of push constants but the device supports at most {}", self.push_constant_size, limits.max_push_constant_size)));
+        }
+
+        if self.staging_buffer_size == 0 || !self.staging_buffer_size.is_multiple_of(16) {
+            return Err(CobraError::InvalidUsage(format!("staging_buffer_size must be a non zero multiple of 16, got {}", self.staging_buffer_size)));
+        }
+
+        let missing_features = self.features.difference(capabilities.features);
+        if !missing_features.is_empty() {
+            return Err(CobraError::Unsupported(format!("The device does not support the requested features {:?}", missing_features)));
+        }
+
+        Ok(())
+    }
 }
 
 // Traits
@@ -119,42 +579,93 @@ pub enum QueueType {
 pub trait ICobra<T>:
     where T: CobraType<T>, Self:Sized, Self:Send, Self:Sync {
 
-    fn new() -> Result<Arc<Self>>;
-    fn new_buffer(&self, cobra: Arc<Self>, size: u64, flags: BufferFlags) -> Result<Buffer<T>>;
+    fn new() -> Result<Arc<Self>> {
+        Self::new_with_desc(CobraDesc::default())
+    }
+    fn new_with_desc(desc: CobraDesc) -> Result<Arc<Self>>;
+    fn enumerate_adapters() -> Result<Vec<AdapterInfo>>;
+
+    fn new_buffer(&self, cobra: Arc<Self>, size: u64, flags: BufferFlags) -> Result<Buffer<T>> {
+        self.new_buffer_with_desc(cobra, BufferDesc::from_flags(size, flags))
+    }
+    fn new_buffer_with_desc(&self, cobra: Arc<Self>, desc: BufferDesc) -> Result<Buffer<T>>;
     fn new_image(&self, cobra: Arc<Self>, size: impl Into<UVec2>, format: ImageFormat, usage: ImageUsage) -> Result<Image<T>>;
     fn new_sampler(&self, cobra: Arc<Self>) -> Result<Sampler<T>>;
-    fn new_swapchain(&self, cobra: Arc<Self>, window: *mut c_void, size: UVec2) -> Result<Swapchain<T>>;
+    fn new_swapchain(&self, cobra: Arc<Self>, window: &(impl HasWindowHandle + HasDisplayHandle), size: UVec2) -> Result<Swapchain<T>>;
+    fn new_fence(&self, cobra: Arc<Self>) -> Result<Arc<Fence<T>>>;
+
+    fn wait_for_fences(&self, fences: &[(&Fence<T>, u64)], mode: WaitMode, timeout: Duration) -> Result<bool>; // false if the timeout elapsed first
 
     fn queue(&self, ty: QueueType) -> &Queue<T>;
 
+    fn adapter(&self) -> &AdapterInfo;
+    fn capabilities(&self) -> &DeviceCapabilities;
     fn supports_resizable_bar(&self) -> bool;
+    fn memory_report(&self) -> Result<MemoryReport>;
 }
 
 // Resources
 pub trait IBuffer<T> 
     where T: CobraType<T>, Self:Sized, Self:Send, Self:Sync {
-    // TODO: maybe allow batching copies together?
-    fn set<U>(&mut self, queue: &mut Queue<T>, source: &[U], buffer_offset: u64) -> Result<()>
+    // Goes through the staging ring without blocking, use an UploadBatch to combine many uploads into one submission.
+    // Fails instead of deadlocking if the ring is full of an upload batch this thread hasn't submitted yet
+    fn set<U>(&mut self, queue: &Queue<T>, source: &[U], buffer_offset: u64) -> Result<SyncPoint<T>>
+        where U: Copy;
+    // Writes straight into mapped memory when the buffer is host visible and goes through staging otherwise, so the
+    // same code works with and without resizable bar. The sync point of a direct write is already complete.
+    // Neither path waits for earlier gpu work, so callers have to sync with anything still using the range before calling
+    fn upload<U>(&mut self, queue: &Queue<T>, source: &[U], buffer_offset: u64) -> Result<SyncPoint<T>>
         where U: Copy;
+    fn is_host_visible(&self) -> bool;
 
-    fn host_address(&self) -> *mut c_void;
+    fn host_address(&self) -> Result<*mut c_void>; // fails when the buffer isn't host visible
     fn device_address(&self) -> u64;
 
-    fn host_slice<U>(&self) -> &mut [U];
+    fn host_slice<U>(&self) -> Result<&[U]>
+        where U: bytemuck::Pod;
     fn size(&self) -> u64;
+
+    // Only needed for non coherent memory, the map guards already do it. Flush after host writes, invalidate before host reads
+    fn flush(&self, offset: u64, size: u64) -> Result<()>;
+    fn invalidate(&self, offset: u64, size: u64) -> Result<()>;
+    fn map_read<U>(&self) -> Result<MapRead<'_, U>>
+        where U: bytemuck::Pod;
+    fn map_write<U>(&mut self) -> Result<MapWrite<'_, T, U>>
+        where U: bytemuck::Pod;
+
+    fn set_name(&mut self, name: &str);
+    fn name(&self) -> Option<&str>;
+    fn with_name(mut self, name: &str) -> Self {
+        self.set_name(name);
+        self
+    }
 }
 
 pub trait IImage<T> 
     where T: CobraType<T>, Self:Sized, Self:Send, Self:Sync {
-    fn set(&mut self, data: &[u8]) -> Result<()>;
+    fn set(&mut self, queue: &Queue<T>, data: &[u8]) -> Result<SyncPoint<T>>; // data is tightly packed, size.x * size.y texels
 
     fn handle(&self) -> Result<u32>;
     fn size(&self) -> UVec2;
+
+    fn set_name(&mut self, name: &str);
+    fn name(&self) -> Option<&str>;
+    fn with_name(mut self, name: &str) -> Self {
+        self.set_name(name);
+        self
+    }
 }
 
 pub trait ISampler<T>
     where T: CobraType<T>, Self:Sized, Self:Send, Self:Sync {
     fn handle(&self) -> u32;
+
+    fn set_name(&mut self, name: &str);
+    fn name(&self) -> Option<&str>;
+    fn with_name(mut self, name: &str) -> Self {
+        self.set_name(name);
+        self
+    }
 }
 
 // Commands and execution
@@ -175,7 +686,15 @@ pub trait ICommandList<T>
     fn end_rendering(&self);
     fn barrier(&self, src: PipelineStage, dst: PipelineStage);
     fn buffer_barrier(&self, buffer: &Buffer<T>, src: PipelineStage, dst: PipelineStage);
-    fn push_constant<U>(&self, value: &U);
+
+    // Ownership transfers are split in two, release is recorded on the current owner and acquire on the new one.
+    // Both are no-ops when the queues share a family
+    fn release_buffer(&self, buffer: &Buffer<T>, dst: QueueType);
+    fn acquire_buffer(&self, buffer: &Buffer<T>, src: QueueType);
+    fn release_image(&self, image: &Image<T>, dst: QueueType);
+    fn acquire_image(&self, image: &Image<T>, src: QueueType);
+
+    fn push_constant<U>(&self, value: &U) -> Result<()>;
 
     fn bind_shaders(&mut self, shaders: &[&'static [u8]]);
     fn bind_index_buffer(&self, buffer: &Buffer<T>, ty: IndexType, offset: u64);
@@ -196,20 +715,84 @@ pub trait ICommandList<T>
 
     fn dispatch(&self, work_x: u32, work_y: u32, work_z: u32);
     fn dispatch_indirect(&self, buffer: &Buffer<T>, offset: u64);
+
+    // Recorded whenever VK_EXT_debug_utils is available, which capture tools provide, so no debug mode is needed to see them
+    fn begin_label(&self, name: &str, color: Vec4);
+    fn end_label(&self);
+    fn insert_label(&self, name: &str, color: Vec4);
+    fn scoped_label(&mut self, name: &str, color: Vec4) -> LabelScope<'_, T, Self>
+        where Self: Sized {
+        self.begin_label(name, color);
+        LabelScope { cmd: self, _phantom: PhantomData }
+    }
 }
 
 pub trait IQueue<T> 
     where T: CobraType<T>, Self:Sized {
     fn acquire(&self, swapchain: &mut Swapchain<T>) -> Result<Option<SyncPoint<T>>>;
-    fn present(&self, swapchain: &mut Swapchain<T>, wait: Option<&mut SyncPoint<T>>) -> Result<()>;
+    fn present(&self, swapchain: &mut Swapchain<T>, wait: Option<&SyncPoint<T>>) -> Result<()>;
 
+    // Resources dropped while a command list is open are only freed once that list was submitted or dropped, so don't keep lists open for long
     fn begin(&self) -> Result<CommandList<T>>;
-    fn submit(&self, cmd: CommandList<T>, wait: Option<&mut SyncPoint<T>>) -> Result<SyncPoint<T>>;
+    fn submit(&self, cmd: CommandList<T>, wait: Option<&SyncPoint<T>>) -> Result<SyncPoint<T>>;
+    fn submit_batch(&self, info: SubmitInfo<'_, T>) -> Result<SyncPoint<T>>;
+
+    // Submit a batch before starting another one on the same thread, a full staging ring can't wait on its own thread
+    fn begin_upload(&self) -> Result<UploadBatch<T>>;
+
+    // Copies into pooled host memory once everything already submitted to this queue and the wait are done
+    fn readback_buffer(&self, buffer: &Buffer<T>, offset: u64, size: u64, wait: Option<&SyncPoint<T>>) -> Result<Readback<T>>;
+    fn readback_image(&self, image: &mut Image<T>, wait: Option<&SyncPoint<T>>) -> Result<Readback<T>>; // tightly packed texels
+}
+
+// Records uploads through the shared staging ring into as few submissions as possible. Data is copied out of the
+// source right away, uploads bigger than the free space in the ring are split up and submitted as it fills.
+// Dropping a batch without submitting discards every write that wasn't submitted yet
+pub trait IUploadBatch<T>
+    where T: CobraType<T>, Self:Sized {
+    fn write_buffer<U>(&mut self, buffer: &Buffer<T>, source: &[U], buffer_offset: u64) -> Result<()>
+        where U: Copy;
+    fn write_image(&mut self, image: &Image<T>, data: &[u8]) -> Result<()>;
+
+    // The sync point covers every upload in the batch, including chunks that were already submitted
+    fn submit(self) -> Result<SyncPoint<T>>;
+}
+
+// Pending copy into host memory, the memory goes back to the pool when dropped
+pub trait IReadback<T>
+    where T: CobraType<T>, Self:Sized {
+    fn sync(&self) -> &SyncPoint<T>;
+    fn size(&self) -> u64;
+    fn is_ready(&self) -> Result<bool> {
+        self.sync().is_complete()
+    }
+
+    fn try_read(&self) -> Result<Option<Vec<u8>>>; // None while the copy is still in flight
+    fn read(self) -> Result<Vec<u8>>; // blocks until the copy is done
+
+    // Trailing bytes that don't make up a whole element are dropped
+    fn try_read_as<U>(&self) -> Result<Option<Vec<U>>>
+        where U: bytemuck::Pod {
+        Ok(self.try_read()?.map(|bytes| bytes_to_vec(&bytes)))
+    }
+
+    fn read_as<U>(self) -> Result<Vec<U>>
+        where U: bytemuck::Pod {
+        Ok(bytes_to_vec(&self.read()?))
+    }
+}
+
+// The bytes of a Vec<u8> aren't aligned for U, so elements are read one at a time
+fn bytes_to_vec<U>(bytes: &[u8]) -> Vec<U>
+    where U: bytemuck::Pod {
+    bytes.chunks_exact(std::mem::size_of::<U>()).map(bytemuck::pod_read_unaligned).collect()
 }
 
-pub trait IFence<T>: 'static 
+pub trait IFence<T>: 'static + Send + Sync
     where T: CobraType<T> {
     fn wait(&self, value: Option<u64>) -> Result<()>;
+    fn wait_timeout(&self, value: u64, timeout: Duration) -> Result<bool>; // false if the timeout elapsed first
+    fn signal(&self, value: u64) -> Result<()>; // signals from the host, the value must be greater than the pending one. Only for user created fences
 
     fn pending_value(&self) -> u64;
     fn current_value(&self) -> Result<u64>;
@@ -222,43 +805,349 @@ pub trait ISwapchain<T>
     fn resize(&mut self, size: UVec2);
     
     fn size(&self) -> UVec2;
+
+    fn set_name(&mut self, name: &str);
+    fn name(&self) -> Option<&str>;
+    fn with_name(mut self, name: &str) -> Self {
+        self.set_name(name);
+        self
+    }
+}
+
+// Mapped memory guards. Reads are invalidated up front, writes are flushed when the guard is dropped
+pub struct MapRead<'a, U> {
+    data: &'a [U]
+}
+
+impl<'a, U> MapRead<'a, U> {
+    #[allow(unused)] // only the vulkan backend maps memory so far
+    pub(crate) fn new(data: &'a [U]) -> Self {
+        MapRead { data }
+    }
+}
+
+impl<U> Deref for MapRead<'_, U> {
+    type Target = [U];
+
+    fn deref(&self) -> &Self::Target {
+        self.data
+    }
+}
+
+pub struct MapWrite<'a, T, U>
+    where T: CobraType<T> {
+    buffer: &'a Buffer<T>,
+    data: &'a mut [U]
+}
+
+impl<'a, T, U> MapWrite<'a, T, U>
+    where T: CobraType<T> {
+    #[allow(unused)] // only the vulkan backend maps memory so far
+    pub(crate) fn new(buffer: &'a Buffer<T>, data: &'a mut [U]) -> Self {
+        MapWrite { buffer, data }
+    }
+}
+
+impl<T, U> Deref for MapWrite<'_, T, U>
+    where T: CobraType<T> {
+    type Target = [U];
+
+    fn deref(&self) -> &Self::Target {
+        self.data
+    }
+}
+
+impl<T, U> DerefMut for MapWrite<'_, T, U>
+    where T: CobraType<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.data
+    }
+}
+
+impl<T, U> Drop for MapWrite<'_, T, U>
+    where T: CobraType<T> {
+    fn drop(&mut self) {
+        if let Err(err) = self.buffer.flush(0, self.buffer.size()) {
+            log::error!(target: "cobra", "Failed to flush mapped buffer writes: {}", err);
+        }
+    }
+}
+
+// Ends the label when dropped, derefs to the command list so recording can continue inside the scope
+pub struct LabelScope<'a, T, C>
+    where T: CobraType<T>, C: ICommandList<T> {
+    cmd: &'a mut C,
+    _phantom: PhantomData<T>
+}
+
+impl<T, C> Deref for LabelScope<'_, T, C>
+    where T: CobraType<T>, C: ICommandList<T> {
+    type Target = C;
+
+    fn deref(&self) -> &Self::Target {
+        self.cmd
+    }
+}
+
+impl<T, C> DerefMut for LabelScope<'_, T, C>
+    where T: CobraType<T>, C: ICommandList<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.cmd
+    }
+}
+
+impl<T, C> Drop for LabelScope<'_, T, C>
+    where T: CobraType<T>, C: ICommandList<T> {
+    fn drop(&mut self) {
+        self.cmd.end_label();
+    }
+}
+
+// Submission
+// Everything in a SubmitInfo goes to the gpu as a single submission, the returned SyncPoint covers all of the command lists
+pub struct SubmitInfo<'a, T>
+    where T: CobraType<T> {
+    pub(crate) command_lists: Vec<CommandList<T>>,
+    pub(crate) waits: Vec<(SyncPoint<T>, PipelineStage)>,
+    pub(crate) signals: Vec<(&'a Fence<T>, u64)>
+}
+
+impl<'a, T> SubmitInfo<'a, T>
+    where T: CobraType<T> {
+    pub fn new() -> Self {
+        SubmitInfo {
+            command_lists: Vec::new(),
+            waits: Vec::new(),
+            signals: Vec::new()
+        }
+    }
+
+    pub fn command_list(mut self, cmd: CommandList<T>) -> Self {
+        self.command_lists.push(cmd);
+        self
+    }
+
+    // The sync point can come from any queue, only the given stages of this submission wait on it
+    pub fn wait(mut self, sync: &SyncPoint<T>, stages: PipelineStage) -> Self {
+        self.waits.push((sync.clone(), stages));
+        self
+    }
+
+    // Same rules as IFence::signal, the value has to be greater than the pending one and the fence user created
+    pub fn signal(mut self, fence: &'a Fence<T>, value: u64) -> Self {
+        self.signals.push((fence, value));
+        self
+    }
+}
+
+impl<T> Default for SubmitInfo<'_, T>
+    where T: CobraType<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 // SyncPoint
+// A set of timeline values to reach, one per fence. Default constructed sync points have nothing to wait on and are always complete
 pub struct SyncPoint<T>
     where T: CobraType<T> {
-    pub fence: Option<*const Fence<T>>,
-    pub value: Option<u64>
+    pub(crate) points: Vec<(Arc<Fence<T>>, u64)>
 }
 
 impl<T> SyncPoint<T> 
     where T: CobraType<T> {
     pub fn new() -> SyncPoint<T> {
         SyncPoint {
-            fence: None,
-            value: None
+            points: Vec::new()
         }
     }
 
-    pub fn new_from_fence(fence: &Fence<T>) -> SyncPoint<T> {
+    pub fn new_from_fence(fence: Arc<Fence<T>>, value: u64) -> SyncPoint<T> {
         SyncPoint {
-            fence: Some(fence as *const _),
-            value: Some(fence.pending_value())
+            points: vec![(fence, value)]
+        }
+    }
+
+    // Completes once every sync point has
+    pub fn join(syncs: impl IntoIterator<Item = SyncPoint<T>>) -> SyncPoint<T> {
+        let mut ret = SyncPoint::new();
+        for sync in syncs {
+            ret.merge(&sync);
+        }
+
+        ret
+    }
+
+    pub fn merge(&mut self, other: &SyncPoint<T>) {
+        for (fence, value) in other.points.iter() {
+            match self.points.iter_mut().find(|point| Arc::ptr_eq(&point.0, fence)) {
+                Some(point) => point.1 = u64::max(point.1, *value),
+                None => self.points.push((fence.clone(), *value))
+            }
         }
     }
 
-    pub fn wait(&mut self) -> Result<()> {
-        if let Some(fence) = self.fence {
-            unsafe { (*fence).wait(self.value)?; }
+    pub fn is_complete(&self) -> Result<bool> {
+        for (fence, value) in self.points.iter() {
+            if fence.current_value()? < *value { return Ok(false); }
+        }
+
+        Ok(true)
+    }
+
+    pub fn wait(&self) -> Result<()> {
+        for (fence, value) in self.points.iter() {
+            fence.wait(Some(*value))?;
         }
 
         Ok(())
     }
 
-    pub fn value(&mut self) -> u64 {
-        match self.value.is_none() && !self.fence.is_some() {
-            true => unsafe { (*self.fence.unwrap()).pending_value() },
-            false => self.value.unwrap()
+    // Returns false if the timeout elapsed before every fence was reached
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<bool> {
+        let deadline = Instant::now() + timeout;
+        for (fence, value) in self.points.iter() {
+            if !fence.wait_timeout(*value, deadline.saturating_duration_since(Instant::now()))? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    pub fn points(&self) -> &[(Arc<Fence<T>>, u64)] {
+        &self.points
+    }
+}
+
+impl<T> Clone for SyncPoint<T>
+    where T: CobraType<T> {
+    fn clone(&self) -> Self {
+        SyncPoint {
+            points: self.points.clone()
         }
     }
-}
\ No newline at end of file
+}
+
+impl<T> Default for SyncPoint<T>
+    where T: CobraType<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adapter(index: usize, name: &str, ty: AdapterType) -> AdapterInfo {
+        AdapterInfo { index, name: name.to_owned(), vendor_id: 0, device_id: 0, ty, driver_version: 0, memory_heaps: Vec::new() }
+    }
+
+    #[test]
+    fn adapter_selection() {
+        let adapters = [
+            adapter(0, "llvmpipe", AdapterType::Cpu),
+            adapter(1, "Intel UHD Graphics", AdapterType::IntegratedGpu),
+            adapter(2, "NVIDIA GeForce RTX", AdapterType::DiscreteGpu)
+        ];
+
+        assert_eq!(AdapterSelection::Default.select(&adapters), Some(2));
+        assert_eq!(AdapterSelection::Default.select(&adapters[..2]), Some(0));
+        assert_eq!(AdapterSelection::Default.select(&[]), None);
+
+        assert_eq!(AdapterSelection::Index(1).select(&adapters), Some(1));
+        assert_eq!(AdapterSelection::Index(3).select(&adapters), None);
+        // Indices are the adapter's own, not positions in the slice
+        assert_eq!(AdapterSelection::Index(2).select(&adapters[1..]), Some(1));
+
+        assert_eq!(AdapterSelection::Name("geforce".to_owned()).select(&adapters), Some(2));
+        assert_eq!(AdapterSelection::Name("AMD".to_owned()).select(&adapters), None);
+
+        assert_eq!(AdapterSelection::Type(AdapterType::IntegratedGpu).select(&adapters), Some(1));
+        assert_eq!(AdapterSelection::Type(AdapterType::VirtualGpu).select(&adapters), None);
+    }
+    fn capabilities(features: DeviceFeatures) -> DeviceCapabilities {
+        DeviceCapabilities {
+            limits: DeviceLimits {
+                max_image_dimension_2d: 16384,
+                max_push_constant_size: 256,
+                max_bindless_samplers: MAX_BINDLESS_SAMPLERS,
+                max_bindless_sampled_images: MAX_BINDLESS_IMAGES,
+                max_bindless_storage_images: 1 << 16,
+                max_compute_workgroup_count: [65535; 3],
+                max_compute_workgroup_size: [1024, 1024, 64],
+                max_compute_workgroup_invocations: 1024,
+                subgroup_size: 32,
+                min_subgroup_size: 32,
+                max_subgroup_size: 32
+            },
+            features,
+            format_usages: HashMap::new()
+        }
+    }
+
+    #[test]
+    fn desc_validation() {
+        let capabilities = capabilities(DeviceFeatures::MemoryBudget);
+        let desc = CobraDesc::default().bindless_images(1 << 16);
+        assert!(desc.validate(&capabilities).is_ok());
+
+        let invalid_usage = [
+            desc.clone().bindless_images(0),
+            desc.clone().bindless_samplers(MAX_BINDLESS_SAMPLERS + 1),
+            desc.clone().push_constant_size(130),
+            desc.clone().staging_buffer_size(0),
+            desc.clone().staging_buffer_size(1000)
+        ];
+        for desc in invalid_usage {
+            assert!(matches!(desc.validate(&capabilities), Err(CobraError::InvalidUsage(_))));
+        }
+
+        // Within the api limits but beyond what the device supports
+        let unsupported = [
+            desc.clone().bindless_images((1 << 16) + 1),
+            desc.clone().push_constant_size(512),
+            desc.clone().features(DeviceFeatures::RayTracing)
+        ];
+        for desc in unsupported {
+            assert!(matches!(desc.validate(&capabilities), Err(CobraError::Unsupported(_))));
+        }
+    }
+    #[test]
+    fn memory_report_json() {
+        let report = MemoryReport {
+            heaps: vec![MemoryHeapReport {
+                size: 1024, device_local: true, usage: 512, budget: 900, block_count: 2, block_bytes: 256,
+                allocations: AllocationStats { count: 3, bytes: 192 }
+            }],
+            buffers: HashMap::from([(BufferFlags::Upload, AllocationStats { count: 1, bytes: 64 })]),
+            images: AllocationStats { count: 2, bytes: 128 }
+        };
+
+        assert_eq!(report.to_json(), concat!(
+            "{\"heaps\":[{\"size\":1024,\"device_local\":true,\"usage\":512,\"budget\":900,\"block_count\":2,\"block_bytes\":256,\"allocations\":{\"count\":3,\"bytes\":192}}],",
+            "\"buffers\":{\"Default\":{\"count\":0,\"bytes\":0},\"Upload\":{\"count\":1,\"bytes\":64},\"Readback\":{\"count\":0,\"bytes\":0},\"DeviceUpload\":{\"count\":0,\"bytes\":0}},",
+            "\"images\":{\"count\":2,\"bytes\":128}}"
+        ));
+    }
+    #[cfg(feature = "vulkan")]
+    #[test]
+    fn sync_point_merge() {
+        use crate::{vulkan::mappings::FenceVulkan, Vulkan};
+
+        let a = Arc::new(FenceVulkan::new());
+        let b = Arc::new(FenceVulkan::new());
+
+        let mut sync = SyncPoint::<Vulkan>::new_from_fence(a.clone(), 3);
+        sync.merge(&SyncPoint::new_from_fence(a.clone(), 1));
+        sync.merge(&SyncPoint::new_from_fence(b.clone(), 2));
+        sync.merge(&SyncPoint::new());
+        let values = |sync: &SyncPoint<Vulkan>| sync.points().iter().map(|(fence, value)| (Arc::as_ptr(fence), *value)).collect::<Vec<_>>();
+        // One point per fence, keeping the highest value
+        assert_eq!(values(&sync), [(Arc::as_ptr(&a), 3), (Arc::as_ptr(&b), 2)]);
+
+        let joined = SyncPoint::join([SyncPoint::new_from_fence(b.clone(), 5), sync.clone(), SyncPoint::new_from_fence(a.clone(), 4)]);
+        assert_eq!(values(&joined), [(Arc::as_ptr(&b), 5), (Arc::as_ptr(&a), 4)]);
+        assert!(SyncPoint::<Vulkan>::join([]).points().is_empty());
+    }
+}
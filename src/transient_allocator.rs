@@ -0,0 +1,115 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::{Buffer, BufferDesc, BufferFlags, Cobra, CobraError, CobraType, IBuffer, ICobra, MemoryPlacement, Result, SyncPoint};
+
+// Covers every type shaders load through a buffer address
+const MIN_ALIGNMENT: u64 = 16;
+
+pub struct TransientAllocation<'a, U> {
+    pub host: &'a mut [U],
+    pub device_address: u64
+}
+
+struct RetiredPage<T>
+    where T: CobraType<T> {
+    buffer: Buffer<T>,
+    sync: SyncPoint<T>
+}
+
+// Linear allocator for data that only lives for a frame, like per draw constants or dynamic geometry. Allocations are carved out of
+// host visible pages, which are reused once the sync point the frame ended with completes
+pub struct TransientAllocator<T>
+    where T: CobraType<T> {
+    cobra: Arc<Cobra<T>>,
+    placement: MemoryPlacement,
+    page_size: u64,
+
+    pages: Vec<Buffer<T>>, // used this frame, the last one is being filled
+    offset: u64,
+    retired: VecDeque<RetiredPage<T>>
+}
+
+impl<T> TransientAllocator<T>
+    where T: CobraType<T> {
+    pub fn new(cobra: Arc<Cobra<T>>, page_size: u64, flags: BufferFlags) -> Result<TransientAllocator<T>> {
+        if !matches!(flags, BufferFlags::Upload | BufferFlags::DeviceUpload) {
+            return Err(CobraError::InvalidUsage(format!("Transient allocations need host visible memory, got {:?}", flags)));
+        }
+        if page_size == 0 {
+            return Err(CobraError::InvalidUsage("page_size must not be zero".to_owned()));
+        }
+
+        // Pages have to stay mappable, so device memory is only used while there's host visible device memory left
+        let placement = match flags {
+            BufferFlags::DeviceUpload => MemoryPlacement::PreferRebar,
+            _ => MemoryPlacement::CpuToGpu
+        };
+
+        Ok(TransientAllocator {
+            cobra, placement, page_size,
+            pages: Vec::new(),
+            offset: 0,
+            retired: VecDeque::new()
+        })
+    }
+
+    // Allocations bigger than a page get a dedicated one
+    pub fn alloc<U>(&mut self, count: usize) -> Result<TransientAllocation<'_, U>>
+        where U: bytemuck::Pod {
+        let size = (count * std::mem::size_of::<U>()) as u64;
+        let alignment = u64::max(std::mem::align_of::<U>() as u64, MIN_ALIGNMENT);
+
+        let mut offset = self.offset.next_multiple_of(alignment);
+        if self.pages.last().is_none_or(|page| offset + size > page.size()) {
+            let page = self.acquire_page(size)?;
+            self.pages.push(page);
+            offset = 0;
+        }
+        self.offset = offset + size;
+
+        let page = self.pages.last().unwrap();
+        unsafe {
+            Ok(TransientAllocation {
+                host: std::slice::from_raw_parts_mut(page.host_address()?.cast::<u8>().add(offset as usize).cast::<U>(), count),
+                device_address: page.device_address() + offset
+            })
+        }
+    }
+
+    // Copies the data into a new allocation and returns its device address
+    pub fn push<U>(&mut self, data: &[U]) -> Result<u64>
+        where U: bytemuck::Pod {
+        let allocation = self.alloc::<U>(data.len())?;
+        allocation.host.copy_from_slice(data);
+
+        Ok(allocation.device_address)
+    }
+
+    // The sync point has to cover all of the gpu work reading this frame's allocations
+    pub fn end_frame(&mut self, sync: &SyncPoint<T>) {
+        for buffer in self.pages.drain(..) {
+            self.retired.push_back(RetiredPage { buffer, sync: sync.clone() });
+        }
+
+        self.offset = 0;
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len() + self.retired.len()
+    }
+
+    fn acquire_page(&mut self, size: u64) -> Result<Buffer<T>> {
+        for i in 0..self.retired.len() {
+            // Pages retire in frame order, so the ones behind an unfinished page can't be done either
+            let page = &self.retired[i];
+            if !page.sync.is_complete()? { break; }
+
+            if page.buffer.size() >= size {
+                return Ok(self.retired.remove(i).unwrap().buffer);
+            }
+        }
+
+        self.cobra.new_buffer_with_desc(self.cobra.clone(), BufferDesc::new(u64::max(size, self.page_size)).placement(self.placement))
+    }
+}
@@ -0,0 +1,219 @@
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+use std::sync::Arc;
+
+use crate::{Buffer, BufferFlags, Cobra, CobraError, CobraType, IBuffer, ICobra, IQueue, IUploadBatch, MapRead, MapWrite, Queue, Result, SyncPoint, UploadBatch};
+
+// A buffer of len elements of U. Everything takes element indices, byte offsets only exist inside
+pub struct TypedBuffer<T, U>
+    where T: CobraType<T>, U: bytemuck::Pod {
+    buffer: Buffer<T>,
+    len: usize,
+    _phantom: PhantomData<U>
+}
+
+impl<T, U> TypedBuffer<T, U>
+    where T: CobraType<T>, U: bytemuck::Pod {
+    pub fn new(cobra: &Arc<Cobra<T>>, len: usize, flags: BufferFlags) -> Result<TypedBuffer<T, U>> {
+        check_len::<U>(len)?;
+
+        Ok(TypedBuffer {
+            buffer: cobra.new_buffer(cobra.clone(), byte_offset::<U>(len), flags)?,
+            len,
+            _phantom: PhantomData
+        })
+    }
+
+    // Any trailing bytes that don't make up a whole element are ignored
+    pub fn from_buffer(buffer: Buffer<T>) -> Result<TypedBuffer<T, U>> {
+        let len = (buffer.size() as usize).checked_div(std::mem::size_of::<U>()).unwrap_or(0);
+        check_len::<U>(len)?;
+
+        Ok(TypedBuffer { buffer, len, _phantom: PhantomData })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn set_range(&self, queue: &Queue<T>, start: usize, data: &[U]) -> Result<SyncPoint<T>> {
+        let mut upload = queue.begin_upload()?;
+        self.write_range(&mut upload, start, data)?;
+        upload.submit()
+    }
+
+    // Records the write into an existing batch instead of submitting on its own
+    pub fn write_range(&self, upload: &mut UploadBatch<T>, start: usize, data: &[U]) -> Result<()> {
+        check_range(start, data.len(), self.len)?;
+        upload.write_buffer(&self.buffer, data, byte_offset::<U>(start))
+    }
+
+    pub fn device_address(&self) -> u64 {
+        self.buffer.device_address()
+    }
+
+    pub fn device_address_of(&self, index: usize) -> Result<u64> {
+        self.as_slice().device_address_of(index)
+    }
+
+    // Fail unless the buffer is host visible
+    pub fn map_read(&self) -> Result<MapRead<'_, U>> {
+        self.buffer.map_read()
+    }
+
+    pub fn map_write(&mut self) -> Result<MapWrite<'_, T, U>> {
+        self.buffer.map_write()
+    }
+
+    pub fn as_slice(&self) -> TypedBufferSlice<'_, T, U> {
+        TypedBufferSlice { buffer: self, start: 0, len: self.len }
+    }
+
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> Result<TypedBufferSlice<'_, T, U>> {
+        self.as_slice().slice(range)
+    }
+
+    pub fn buffer(&self) -> &Buffer<T> {
+        &self.buffer
+    }
+
+    pub fn into_inner(self) -> Buffer<T> {
+        self.buffer
+    }
+}
+
+// A view of a range of elements, for handing parts of a buffer to shaders or copies
+pub struct TypedBufferSlice<'a, T, U>
+    where T: CobraType<T>, U: bytemuck::Pod {
+    buffer: &'a TypedBuffer<T, U>,
+    start: usize,
+    len: usize
+}
+
+impl<'a, T, U> TypedBufferSlice<'a, T, U>
+    where T: CobraType<T>, U: bytemuck::Pod {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Index of the first element in the whole buffer
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn byte_offset(&self) -> u64 {
+        byte_offset::<U>(self.start)
+    }
+
+    pub fn byte_size(&self) -> u64 {
+        byte_offset::<U>(self.len)
+    }
+
+    pub fn device_address(&self) -> u64 {
+        self.buffer.buffer.device_address() + self.byte_offset()
+    }
+
+    pub fn device_address_of(&self, index: usize) -> Result<u64> {
+        check_range(index, 1, self.len)?;
+        Ok(self.device_address() + byte_offset::<U>(index))
+    }
+
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> Result<TypedBufferSlice<'a, T, U>> {
+        let (start, end) = resolve_range(range, self.len)?;
+        Ok(TypedBufferSlice { buffer: self.buffer, start: self.start + start, len: end - start })
+    }
+
+    pub fn buffer(&self) -> &'a Buffer<T> {
+        &self.buffer.buffer
+    }
+}
+
+impl<T, U> Clone for TypedBufferSlice<'_, T, U>
+    where T: CobraType<T>, U: bytemuck::Pod {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, U> Copy for TypedBufferSlice<'_, T, U>
+    where T: CobraType<T>, U: bytemuck::Pod { }
+
+fn check_len<U>(len: usize) -> Result<()> {
+    if len == 0 || std::mem::size_of::<U>() == 0 {
+        return Err(CobraError::InvalidUsage("Typed buffers need at least one non zero sized element".to_owned()));
+    }
+    match (len as u64).checked_mul(std::mem::size_of::<U>() as u64) {
+        Some(_) => Ok(()),
+        None => Err(CobraError::InvalidUsage(format!("{} elements of {} bytes don't fit in a buffer", len, std::mem::size_of::<U>())))
+    }
+}
+
+fn check_range(start: usize, count: usize, len: usize) -> Result<()> {
+    match start.checked_add(count) {
+        Some(end) if end <= len => Ok(()),
+        _ => Err(CobraError::InvalidUsage(format!("Elements {}..{} are out of range for a buffer of {} elements", start, start.saturating_add(count), len)))
+    }
+}
+
+// Turns the bounds into start..end, rejecting ranges that are reversed or reach past len
+fn resolve_range(range: impl RangeBounds<usize>, len: usize) -> Result<(usize, usize)> {
+    let start = match range.start_bound() {
+        Bound::Included(start) => Some(*start),
+        Bound::Excluded(start) => start.checked_add(1),
+        Bound::Unbounded => Some(0)
+    };
+    let end = match range.end_bound() {
+        Bound::Included(end) => end.checked_add(1),
+        Bound::Excluded(end) => Some(*end),
+        Bound::Unbounded => Some(len)
+    };
+
+    match (start, end) {
+        (Some(start), Some(end)) if start <= end && end <= len => Ok((start, end)),
+        _ => Err(CobraError::InvalidUsage(format!("Elements {}..{} are out of range for a slice of {} elements", start.unwrap_or(usize::MAX), end.unwrap_or(usize::MAX), len)))
+    }
+}
+
+// Indices never go past the length, which check_len made sure fits in a u64 byte size
+fn byte_offset<U>(index: usize) -> u64 {
+    (index as u64).checked_mul(std::mem::size_of::<U>() as u64).expect("element offset overflowed a u64")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges() {
+        assert!(check_range(0, 4, 4).is_ok());
+        assert!(check_range(4, 0, 4).is_ok());
+        assert!(check_range(3, 2, 4).is_err());
+        assert!(check_range(usize::MAX, 2, 4).is_err());
+
+        assert_eq!(resolve_range(.., 4).unwrap(), (0, 4));
+        assert_eq!(resolve_range(1..3, 4).unwrap(), (1, 3));
+        assert_eq!(resolve_range(1..=3, 4).unwrap(), (1, 4));
+        assert_eq!(resolve_range(4.., 4).unwrap(), (4, 4));
+        assert!(resolve_range((Bound::Included(3), Bound::Excluded(2)), 4).is_err());
+        assert!(resolve_range(..=4, 4).is_err());
+        assert!(resolve_range(..=usize::MAX, 4).is_err());
+    }
+
+    #[test]
+    fn lengths() {
+        assert!(check_len::<u32>(1).is_ok());
+        assert!(check_len::<u32>(0).is_err());
+        assert!(check_len::<()>(1).is_err());
+        assert!(check_len::<[u8; 16]>(usize::MAX).is_err());
+
+        assert_eq!(byte_offset::<u32>(3), 12);
+    }
+}
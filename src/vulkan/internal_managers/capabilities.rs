@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::ffi::CStr;
+
+use ash::vk;
+
+use crate::{vulkan::mappings::CobraVulkan, DeviceCapabilities, DeviceFeatures, DeviceLimits, ImageFormat, ImageUsage, Result};
+
+use super::utils;
+
+const IMAGE_FORMATS: [ImageFormat; 6] = [
+    ImageFormat::R32Sint,
+    ImageFormat::R16G16B16A16Sfloat,
+    ImageFormat::R16G16B16A16Unorm,
+    ImageFormat::R8G8B8A8Unorm,
+    ImageFormat::B8G8R8A8Srgb,
+    ImageFormat::D32SFloat
+];
+
+impl CobraVulkan {
+
+    pub(crate) fn query_capabilities(instance: &ash::Instance, chosen_gpu: vk::PhysicalDevice) -> Result<DeviceCapabilities> {
+        unsafe {
+            let mut properties11 = vk::PhysicalDeviceVulkan11Properties::default();
+            let mut properties12 = vk::PhysicalDeviceVulkan12Properties::default();
+            let mut properties13 = vk::PhysicalDeviceVulkan13Properties::default();
+            let mut properties = vk::PhysicalDeviceProperties2::default()
+                .push_next(&mut properties11)
+                .push_next(&mut properties12)
+                .push_next(&mut properties13);
+            instance.get_physical_device_properties2(chosen_gpu, &mut properties);
+            let device_limits = properties.properties.limits;
+
+            let mut features12 = vk::PhysicalDeviceVulkan12Features::default();
+            let mut features = vk::PhysicalDeviceFeatures2::default()
+                .push_next(&mut features12);
+            instance.get_physical_device_features2(chosen_gpu, &mut features);
+            let core_features = features.features;
+
+            let extensions = instance.enumerate_device_extension_properties(chosen_gpu)?;
+            let has_extension = |name: &CStr| extensions.iter().any(|extension| extension.extension_name_as_c_str() == Ok(name));
+
+            let limits = DeviceLimits {
+                max_image_dimension_2d: device_limits.max_image_dimension2_d,
+                max_push_constant_size: device_limits.max_push_constants_size,
+
+                max_bindless_samplers: u32::min(properties12.max_per_stage_descriptor_update_after_bind_samplers, properties12.max_descriptor_set_update_after_bind_samplers),
+                max_bindless_sampled_images: u32::min(properties12.max_per_stage_descriptor_update_after_bind_sampled_images, properties12.max_descriptor_set_update_after_bind_sampled_images),
+                max_bindless_storage_images: u32::min(properties12.max_per_stage_descriptor_update_after_bind_storage_images, properties12.max_descriptor_set_update_after_bind_storage_images),
+
+                max_compute_workgroup_count: device_limits.max_compute_work_group_count,
+                max_compute_workgroup_size: device_limits.max_compute_work_group_size,
+                max_compute_workgroup_invocations: device_limits.max_compute_work_group_invocations,
+
+                subgroup_size: properties11.subgroup_size,
+                min_subgroup_size: properties13.min_subgroup_size,
+                max_subgroup_size: properties13.max_subgroup_size
+            };
+
+            let mut device_features = DeviceFeatures::empty();
+            device_features.set(DeviceFeatures::ResizableBar, Self::has_resizable_bar(instance, chosen_gpu));
+            device_features.set(DeviceFeatures::MemoryBudget, has_extension(ash::ext::memory_budget::NAME));
+            device_features.set(DeviceFeatures::ShaderInt64, core_features.shader_int64 == vk::TRUE);
+            device_features.set(DeviceFeatures::ShaderInt16, core_features.shader_int16 == vk::TRUE);
+            device_features.set(DeviceFeatures::ShaderInt8, features12.shader_int8 == vk::TRUE);
+            device_features.set(DeviceFeatures::ShaderFloat64, core_features.shader_float64 == vk::TRUE);
+            device_features.set(DeviceFeatures::ShaderFloat16, features12.shader_float16 == vk::TRUE);
+            device_features.set(DeviceFeatures::MultiDrawIndirect, core_features.multi_draw_indirect == vk::TRUE);
+            device_features.set(DeviceFeatures::DrawIndirectCount, features12.draw_indirect_count == vk::TRUE);
+            device_features.set(DeviceFeatures::SamplerAnisotropy, core_features.sampler_anisotropy == vk::TRUE);
+            device_features.set(DeviceFeatures::MeshShader, has_extension(ash::ext::mesh_shader::NAME));
+            device_features.set(DeviceFeatures::RayTracing,
+                has_extension(ash::khr::ray_tracing_pipeline::NAME) &&
+                has_extension(ash::khr::acceleration_structure::NAME) &&
+                has_extension(ash::khr::deferred_host_operations::NAME)
+            );
+
+            let mut format_usages = HashMap::new();
+            for format in IMAGE_FORMATS {
+                let format_properties = instance.get_physical_device_format_properties(chosen_gpu, utils::image_format_to_vulkan(format));
+                format_usages.insert(format, format_features_to_usage(format_properties.optimal_tiling_features));
+            }
+
+            Ok(DeviceCapabilities {
+                limits, format_usages,
+                features: device_features
+            })
+        }
+    }
+
+    fn has_resizable_bar(instance: &ash::Instance, chosen_gpu: vk::PhysicalDevice) -> bool {
+        unsafe {
+            let memory_properties = instance.get_physical_device_memory_properties(chosen_gpu);
+
+            let mut max_device_memory = 0;
+            for heap in memory_properties.memory_heaps_as_slice() {
+                if heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL) {
+                    max_device_memory = std::cmp::max(max_device_memory, heap.size);
+                }
+            }
+
+            let mut max_host_visible_device_memory = 0;
+            for ty in memory_properties.memory_types_as_slice() {
+                if ty.property_flags.contains(vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::HOST_VISIBLE) {
+                    max_host_visible_device_memory = std::cmp::max(max_host_visible_device_memory, memory_properties.memory_heaps[ty.heap_index as usize].size);
+                }
+            }
+
+            max_device_memory == max_host_visible_device_memory
+        }
+    }
+
+}
+
+fn format_features_to_usage(features: vk::FormatFeatureFlags) -> ImageUsage {
+    let mut ret = ImageUsage::None;
+    if features.contains(vk::FormatFeatureFlags::COLOR_ATTACHMENT) { ret |= ImageUsage::ColorAttachment; }
+    if features.contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT) { ret |= ImageUsage::DepthStencilAttachment; }
+    if features.contains(vk::FormatFeatureFlags::TRANSFER_SRC) { ret |= ImageUsage::TransferSrc; }
+    if features.contains(vk::FormatFeatureFlags::TRANSFER_DST) { ret |= ImageUsage::TransferDst; }
+    if features.contains(vk::FormatFeatureFlags::STORAGE_IMAGE) { ret |= ImageUsage::Storage; }
+    if features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE) { ret |= ImageUsage::Sampled; }
+
+    ret
+}
@@ -0,0 +1,108 @@
+use std::ffi::{c_void, CStr, CString};
+
+use ash::vk;
+
+use crate::{vulkan::mappings::CobraVulkan, DebugCallback, DebugDesc, DebugSeverity, Result};
+
+pub(crate) const VALIDATION_LAYER_NAME: &CStr = c"VK_LAYER_KHRONOS_validation";
+
+struct DebugUserData {
+    callback: Option<DebugCallback>
+}
+
+pub(crate) struct DebugMessenger {
+    pub(crate) instance_fn: ash::ext::debug_utils::Instance,
+    messenger: vk::DebugUtilsMessengerEXT,
+
+    // Boxed so the pointer handed to the driver stays valid for the messenger's lifetime
+    _user_data: Box<DebugUserData>
+}
+
+impl DebugMessenger {
+    pub(crate) fn new(entry: &ash::Entry, instance: &ash::Instance, desc: &DebugDesc) -> Result<DebugMessenger> {
+        unsafe {
+            let instance_fn = ash::ext::debug_utils::Instance::new(entry, instance);
+            let mut user_data = Box::new(DebugUserData { callback: desc.callback.clone() });
+
+            let messenger = instance_fn.create_debug_utils_messenger(&vk::DebugUtilsMessengerCreateInfoEXT::default()
+                .message_severity(severity_mask(desc.min_severity))
+                .message_type(
+                    vk::DebugUtilsMessageTypeFlagsEXT::GENERAL |
+                    vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION |
+                    vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
+                )
+                .pfn_user_callback(Some(debug_callback))
+                .user_data(user_data.as_mut() as *mut DebugUserData as *mut c_void)
+            , None)?;
+
+            Ok(DebugMessenger {
+                instance_fn, messenger,
+                _user_data: user_data
+            })
+        }
+    }
+
+    pub(crate) fn destroy(&self) {
+        unsafe {
+            self.instance_fn.destroy_debug_utils_messenger(self.messenger, None);
+        }
+    }
+}
+
+impl CobraVulkan {
+
+    pub(crate) fn set_object_name(&self, handle: impl vk::Handle, name: &str) {
+        let Some(debug_utils_fn) = &self.debug_utils_fn else { return; };
+
+        // Names with interior nul bytes can't be passed to Vulkan, just leave those objects unnamed
+        if let Ok(name) = CString::new(name) {
+            unsafe {
+                let _ = debug_utils_fn.set_debug_utils_object_name(&vk::DebugUtilsObjectNameInfoEXT::default()
+                    .object_handle(handle)
+                    .object_name(&name)
+                );
+            }
+        }
+    }
+
+}
+
+fn severity_mask(min_severity: DebugSeverity) -> vk::DebugUtilsMessageSeverityFlagsEXT {
+    let mut ret = vk::DebugUtilsMessageSeverityFlagsEXT::ERROR;
+    if min_severity <= DebugSeverity::Warning { ret |= vk::DebugUtilsMessageSeverityFlagsEXT::WARNING; }
+    if min_severity <= DebugSeverity::Info { ret |= vk::DebugUtilsMessageSeverityFlagsEXT::INFO; }
+    if min_severity <= DebugSeverity::Verbose { ret |= vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE; }
+
+    ret
+}
+
+unsafe extern "system" fn debug_callback(
+    severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    _ty: vk::DebugUtilsMessageTypeFlagsEXT,
+    callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT<'_>,
+    user_data: *mut c_void
+) -> vk::Bool32 {
+    let severity = match severity {
+        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => DebugSeverity::Error,
+        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => DebugSeverity::Warning,
+        vk::DebugUtilsMessageSeverityFlagsEXT::INFO => DebugSeverity::Info,
+        _ => DebugSeverity::Verbose
+    };
+
+    let message = match callback_data.as_ref().and_then(|data| data.message_as_c_str()) {
+        Some(message) => message.to_string_lossy(),
+        None => return vk::FALSE
+    };
+
+    match (user_data as *const DebugUserData).as_ref().and_then(|data| data.callback.as_ref()) {
+        Some(callback) => callback(severity, &message),
+        None => log::log!(target: "cobra", match severity {
+            DebugSeverity::Error => log::Level::Error,
+            DebugSeverity::Warning => log::Level::Warn,
+            DebugSeverity::Info => log::Level::Info,
+            DebugSeverity::Verbose => log::Level::Trace
+        }, "{}", message)
+    }
+
+    vk::FALSE
+}
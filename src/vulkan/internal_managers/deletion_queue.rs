@@ -1,5 +1,25 @@
+use std::collections::VecDeque;
+
 use ash::vk;
 use super::super::mappings::CobraVulkan;
+use crate::{IFence, Result};
+
+pub(crate) const QUEUE_COUNT: usize = 3;
+
+// Pending timeline value of each queue (graphics, compute, transfer) once everything that could use the value was submitted
+pub(crate) type TimelineValues = [u64; QUEUE_COUNT];
+
+enum DeletionWait {
+    CommandLists(Vec<u64>), // ids of the command lists that were open at the push and haven't been submitted or dropped yet
+    Values(TimelineValues)
+}
+
+#[derive(Default)]
+pub(crate) struct DeletionQueue {
+    entries: VecDeque<(DeletionWait, DeleteValue)>,
+    open_command_lists: Vec<u64>,
+    next_command_list_id: u64
+}
 
 pub enum DeleteValue {
     Swapchain(vk::SwapchainKHR),
@@ -67,28 +87,139 @@ impl From<vk::ShaderModule> for DeleteValue {
     }
 }
 
+impl DeletionQueue {
+    // Open command lists might still record uses of the value and get submitted at any later point, so the value is only
+    // tagged with the pending timeline values once every list that was open at the push is gone
+    fn push(&mut self, to_delete: DeleteValue, pending_values: TimelineValues) {
+        let wait = match self.open_command_lists.is_empty() {
+            true => DeletionWait::Values(pending_values),
+            false => DeletionWait::CommandLists(self.open_command_lists.clone())
+        };
+        self.entries.push_back((wait, to_delete));
+    }
+
+    fn open_command_list(&mut self) -> u64 {
+        let id = self.next_command_list_id;
+        self.next_command_list_id += 1;
+        self.open_command_lists.push(id);
+        id
+    }
+
+    fn close_command_list(&mut self, id: u64, pending_values: TimelineValues) {
+        self.open_command_lists.retain(|open| *open != id);
+        for (wait, _) in self.entries.iter_mut() {
+            let DeletionWait::CommandLists(ids) = wait else { continue; };
+            ids.retain(|open| *open != id);
+            if ids.is_empty() {
+                *wait = DeletionWait::Values(pending_values);
+            }
+        }
+    }
+
+    // Entries wait on different command lists so they aren't in timeline order, the whole queue is scanned
+    fn collect(&mut self, completed_values: TimelineValues, mut destroy: impl FnMut(&mut DeleteValue)) {
+        self.entries.retain_mut(|(wait, to_delete)| {
+            let DeletionWait::Values(values) = wait else { return true; };
+            if values.iter().zip(completed_values).any(|(value, completed_value)| *value > completed_value) { return true; }
+
+            destroy(to_delete);
+            false
+        });
+    }
+}
+
 impl CobraVulkan {
 
-    pub(crate) fn push(&self, to_delete: impl Into<DeleteValue>) { self.deletion_queue.lock().unwrap().push(to_delete.into()); }
+    pub(crate) fn push(&self, to_delete: impl Into<DeleteValue>) {
+        self.deletion_queue.lock().unwrap().push(to_delete.into(), self.pending_values());
+    }
+
+    pub(crate) fn open_command_list(&self) -> u64 {
+        self.deletion_queue.lock().unwrap().open_command_list()
+    }
+
+    // Has to be called after the list was submitted so the pending values cover it
+    pub(crate) fn close_command_list(&self, id: u64) {
+        self.deletion_queue.lock().unwrap().close_command_list(id, self.pending_values());
+    }
+
+    // Destroys everything the gpu has finished with
+    pub(crate) fn collect(&self) -> Result<()> {
+        let queues = self.queues();
+        let mut completed_values = [0; QUEUE_COUNT];
+        for (completed_value, queue) in completed_values.iter_mut().zip(queues) {
+            *completed_value = queue.fence.current_value()?;
+        }
+
+        self.deletion_queue.lock().unwrap().collect(completed_values, |to_delete| self.destroy(to_delete));
+        Ok(())
+    }
 
     pub(crate) fn flush(&mut self) {
+        let mut deletion_queue = std::mem::take(&mut *self.deletion_queue.lock().unwrap());
+        for (_, value) in deletion_queue.entries.iter_mut() {
+            self.destroy(value);
+        }
+    }
+
+    fn pending_values(&self) -> TimelineValues {
+        self.queues().map(|queue| queue.fence.pending_value())
+    }
+
+    fn destroy(&self, value: &mut DeleteValue) {
         unsafe {
-            let mut deletion_queue = self.deletion_queue.lock().unwrap();
-            for value in deletion_queue.iter_mut() {
-                match value {
-                    DeleteValue::Swapchain(swapchain) => self.swapchain_device_fn.destroy_swapchain(*swapchain, None),
-                    DeleteValue::Surface(surface) => self.surface_fn.destroy_surface(*surface, None),
-                    DeleteValue::CommandPool(pool) => self.device.destroy_command_pool(*pool, None),
-                    DeleteValue::Semaphore(semaphore) => self.device.destroy_semaphore(*semaphore, None),
-                    DeleteValue::ImageView(image_view) => self.device.destroy_image_view(*image_view, None),
-                    DeleteValue::Image(image) => self.allocator.destroy_image(image.0, &mut image.1),
-                    DeleteValue::Buffer(buffer) => self.allocator.destroy_buffer(buffer.0, &mut buffer.1),
-                    DeleteValue::Sampler(sampler) => self.device.destroy_sampler(*sampler, None),
-                    DeleteValue::ShaderModule(module) => self.device.destroy_shader_module(*module, None)
-                }
+            match value {
+                DeleteValue::Swapchain(swapchain) => self.wsi().swapchain_device_fn.destroy_swapchain(*swapchain, None),
+                DeleteValue::Surface(surface) => self.wsi().surface_fn.destroy_surface(*surface, None),
+                DeleteValue::CommandPool(pool) => self.device.destroy_command_pool(*pool, None),
+                DeleteValue::Semaphore(semaphore) => self.device.destroy_semaphore(*semaphore, None),
+                DeleteValue::ImageView(image_view) => self.device.destroy_image_view(*image_view, None),
+                DeleteValue::Image(image) => self.allocator.destroy_image(image.0, &mut image.1),
+                DeleteValue::Buffer(buffer) => self.allocator.destroy_buffer(buffer.0, &mut buffer.1),
+                DeleteValue::Sampler(sampler) => self.device.destroy_sampler(*sampler, None),
+                DeleteValue::ShaderModule(module) => self.device.destroy_shader_module(*module, None)
             }
-            deletion_queue.clear();
         }
     }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collected(deletion_queue: &mut DeletionQueue, completed_values: TimelineValues) -> usize {
+        let mut count = 0;
+        deletion_queue.collect(completed_values, |_| count += 1);
+        count
+    }
+
+    #[test]
+    fn waits_for_the_pending_values() {
+        let mut deletion_queue = DeletionQueue::default();
+        deletion_queue.push(vk::Semaphore::null().into(), [2, 0, 1]);
+
+        assert_eq!(collected(&mut deletion_queue, [2, 0, 0]), 0);
+        assert_eq!(collected(&mut deletion_queue, [2, 0, 1]), 1);
+        assert!(deletion_queue.entries.is_empty());
+    }
+
+    #[test]
+    fn waits_only_for_lists_open_at_the_push() {
+        let mut deletion_queue = DeletionQueue::default();
+        let early = deletion_queue.open_command_list();
+        deletion_queue.push(vk::Semaphore::null().into(), [1, 0, 0]);
+        let late = deletion_queue.open_command_list();
+        deletion_queue.push(vk::Semaphore::null().into(), [1, 0, 0]);
+
+        // The late list stays open, which only holds back the second entry
+        deletion_queue.close_command_list(early, [3, 0, 0]);
+        assert_eq!(collected(&mut deletion_queue, [2, 0, 0]), 0);
+        assert_eq!(collected(&mut deletion_queue, [3, 0, 0]), 1);
+
+        deletion_queue.close_command_list(late, [4, 0, 0]);
+        assert_eq!(collected(&mut deletion_queue, [3, 0, 0]), 0);
+        assert_eq!(collected(&mut deletion_queue, [4, 0, 0]), 1);
+        assert!(deletion_queue.open_command_lists.is_empty());
+    }
+}
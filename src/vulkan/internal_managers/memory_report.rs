@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+
+use ash::vk;
+
+use crate::{vulkan::mappings::CobraVulkan, AllocationStats, BufferFlags, MemoryHeapReport, MemoryReport, Result};
+
+#[derive(Clone, Copy)]
+pub(crate) enum ResourceMemoryType {
+    Buffer(BufferFlags),
+    Image
+}
+
+// The allocator only knows about heaps, so totals per resource type are tracked on creation and destruction
+#[derive(Default)]
+pub(crate) struct ResourceMemory {
+    buffers: HashMap<BufferFlags, AllocationStats>,
+    images: AllocationStats
+}
+
+impl ResourceMemory {
+    fn stats_mut(&mut self, ty: ResourceMemoryType) -> &mut AllocationStats {
+        match ty {
+            ResourceMemoryType::Buffer(flags) => self.buffers.entry(flags).or_default(),
+            ResourceMemoryType::Image => &mut self.images
+        }
+    }
+}
+
+impl CobraVulkan {
+
+    pub(crate) fn track_allocation(&self, ty: ResourceMemoryType, bytes: u64) {
+        let mut resource_memory = self.resource_memory.lock().unwrap();
+        let stats = resource_memory.stats_mut(ty);
+
+        stats.count += 1;
+        stats.bytes += bytes;
+    }
+
+    pub(crate) fn untrack_allocation(&self, ty: ResourceMemoryType, bytes: u64) {
+        let mut resource_memory = self.resource_memory.lock().unwrap();
+        let stats = resource_memory.stats_mut(ty);
+
+        stats.count -= 1;
+        stats.bytes -= bytes;
+    }
+
+    pub(crate) fn query_memory_report(&self) -> Result<MemoryReport> {
+        unsafe {
+            let memory_properties = self.instance.get_physical_device_memory_properties(self.chosen_gpu);
+            let budgets = self.allocator.get_heap_budgets()?;
+
+            let heaps = memory_properties.memory_heaps_as_slice().iter().zip(budgets.iter()).map(|(heap, budget)| MemoryHeapReport {
+                size: heap.size,
+                device_local: heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL),
+
+                usage: budget.usage,
+                budget: budget.budget,
+
+                block_count: budget.statistics.blockCount as u64,
+                block_bytes: budget.statistics.blockBytes,
+                allocations: AllocationStats {
+                    count: budget.statistics.allocationCount as u64,
+                    bytes: budget.statistics.allocationBytes
+                }
+            }).collect();
+
+            let resource_memory = self.resource_memory.lock().unwrap();
+            Ok(MemoryReport {
+                heaps,
+                buffers: resource_memory.buffers.clone(),
+                images: resource_memory.images
+            })
+        }
+    }
+
+}
@@ -1,4 +1,9 @@
 pub mod utils;
 pub mod pipeline_manager;
 pub mod deletion_queue;
-pub mod resource_handle;
\ No newline at end of file
+pub mod resource_handle;
+pub mod capabilities;
+pub mod debug_utils;
+pub mod memory_report;
+pub mod staging_ring;
+pub mod readback_pool;
\ No newline at end of file
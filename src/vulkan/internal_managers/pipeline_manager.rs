@@ -1,8 +1,7 @@
-use anyhow::Result;
 use ash::vk;
 use spirv_cross2::spirv::ExecutionModel;
 
-use crate::{vulkan::mappings::CobraVulkan, BlendFactor, BlendOp, ImageFormat};
+use crate::{vulkan::mappings::CobraVulkan, BlendFactor, BlendOp, ImageFormat, Result};
 
 use super::utils;
 
@@ -116,9 +115,11 @@ impl CobraVulkan {
                             .color_attachment_formats(&[utils::image_format_to_vulkan(key.color_attachment)])
                             .depth_attachment_format(utils::image_format_to_vulkan(key.depth_attachment))
                         )
-                    ], None).unwrap()[0];
+                    ], None).map_err(|(_, result)| result)?[0];
 
-                    self.graphics_pipelines.write().unwrap().insert(key, pipeline);
+                    let mut graphics_pipelines = self.graphics_pipelines.write().unwrap();
+                    self.set_object_name(pipeline, &format!("Graphics pipeline {} ({:?}, {:?})", graphics_pipelines.len(), key.color_attachment, key.depth_attachment));
+                    graphics_pipelines.insert(key, pipeline);
                     pipeline
                 }
             });
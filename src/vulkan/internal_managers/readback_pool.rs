@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use std::mem::ManuallyDrop;
+use std::sync::Weak;
+
+use crate::{vulkan::mappings::{BufferVulkan, CobraVulkan}, BufferDesc, BufferUsage, IBuffer, MemoryPlacement, Result, SyncPoint, Vulkan};
+
+const MIN_READBACK_SIZE: u64 = 64 * 1024;
+// Buffers kept per bucket, a burst of readbacks shouldn't hold on to its memory forever
+const MAX_POOLED_PER_BUCKET: usize = 4;
+
+// Buffer and the copy into it that has to finish before it can be reused
+type PooledBuffer = (ManuallyDrop<BufferVulkan>, SyncPoint<Vulkan>);
+
+// Readback buffers are kept around after use, bucketed by power of two size. Like the staging buffer they only hold a weak
+// reference to the context and are destroyed manually, otherwise the pool would keep the context alive
+#[derive(Default)]
+pub(crate) struct ReadbackPool {
+    buffers: HashMap<u64, Vec<PooledBuffer>>
+}
+
+impl ReadbackPool {
+    pub(crate) fn drain(&mut self) -> impl Iterator<Item = ManuallyDrop<BufferVulkan>> + '_ {
+        self.buffers.drain().flat_map(|(_, buffers)| buffers).map(|(buffer, _)| buffer)
+    }
+}
+
+impl CobraVulkan {
+
+    pub(crate) fn acquire_readback_buffer(&self, cobra: &Weak<CobraVulkan>, size: u64) -> Result<ManuallyDrop<BufferVulkan>> {
+        let bucket = u64::max(size.next_power_of_two(), MIN_READBACK_SIZE);
+        if let Some(buffers) = self.readback_pool.lock().unwrap().buffers.get_mut(&bucket) {
+            // The previous copy into a buffer might still be in flight
+            for index in 0..buffers.len() {
+                if buffers[index].1.is_complete()? {
+                    return Ok(buffers.remove(index).0);
+                }
+            }
+        }
+
+        Ok(ManuallyDrop::new(BufferVulkan::new_weak(cobra, BufferDesc::new(bucket)
+            .usage(BufferUsage::TransferDst)
+            .placement(MemoryPlacement::GpuToCpu)
+        )?))
+    }
+
+    pub(crate) fn release_readback_buffer(&self, buffer: ManuallyDrop<BufferVulkan>, sync: SyncPoint<Vulkan>) {
+        let mut readback_pool = self.readback_pool.lock().unwrap();
+        let buffers = readback_pool.buffers.entry(buffer.size()).or_default();
+        buffers.push((buffer, sync));
+
+        // The oldest one goes, the deletion queue keeps it alive until a copy still in flight is done
+        if buffers.len() > MAX_POOLED_PER_BUCKET {
+            ManuallyDrop::into_inner(buffers.remove(0).0);
+        }
+    }
+
+}
@@ -19,9 +19,7 @@ impl ResourceHandle {
     pub fn new(cobra: Arc<CobraVulkan>, ty: ResourceType) -> ResourceHandle {
         // create key if doesnt exist (first time only)
         let mut id_infos = cobra.id_infos.lock().unwrap();
-        if !id_infos.contains_key(&ty) {
-            id_infos.insert(ty, IDInfo { id_counter: 0, recycled_ids: Vec::new() });
-        }
+        id_infos.entry(ty).or_insert_with(|| IDInfo { id_counter: 0, recycled_ids: Vec::new() });
 
         let id = if id_infos[&ty].recycled_ids.is_empty() {
             let id = id_infos[&ty].id_counter;
@@ -0,0 +1,175 @@
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use std::thread::ThreadId;
+
+use crate::{CobraError, Result, SyncPoint, Vulkan};
+
+// Covers the copy offset requirements of every supported image format
+pub(crate) const STAGING_ALIGNMENT: u64 = 16;
+
+struct Reservation {
+    id: u64,
+    end: u64,
+    thread: ThreadId,
+    sync: Option<SyncPoint<Vulkan>> // None until the upload that owns it has been submitted
+}
+
+struct RingState {
+    // Both only ever grow, the physical offset is the value modulo the ring size
+    head: u64,
+    tail: u64,
+    next_id: u64,
+    reservations: VecDeque<Reservation>
+}
+
+// Hands out regions of the staging buffer in order, a region is reused once the gpu has passed the submission that read from it
+pub(crate) struct StagingRing {
+    size: u64,
+    state: Mutex<RingState>,
+    submitted: Condvar
+}
+
+impl StagingRing {
+    pub(crate) fn new(size: u64) -> StagingRing {
+        StagingRing {
+            size,
+            state: Mutex::new(RingState {
+                head: 0,
+                tail: 0,
+                next_id: 0,
+                reservations: VecDeque::new()
+            }),
+            submitted: Condvar::new()
+        }
+    }
+
+    // Keeps a few chunks in flight at once, so one big upload doesn't stall everyone else until it's done
+    pub(crate) fn max_chunk(&self) -> u64 {
+        (self.size / 4).next_multiple_of(STAGING_ALIGNMENT)
+    }
+
+    // Returns the reservation id and offset into the staging buffer, or None if the ring is currently full
+    pub(crate) fn try_allocate(&self, size: u64) -> Result<Option<(u64, u64)>> {
+        let mut state = self.state.lock().unwrap();
+        while let Some(Reservation { end, sync: Some(sync), .. }) = state.reservations.front() {
+            if !sync.is_complete()? { break; }
+
+            state.tail = *end;
+            state.reservations.pop_front();
+        }
+
+        if state.reservations.is_empty() {
+            state.head = 0;
+            state.tail = 0;
+        }
+
+        let head = state.head.next_multiple_of(STAGING_ALIGNMENT);
+        let physical = head % self.size;
+        let start = match physical + size > self.size {
+            true => head + (self.size - physical), // doesn't fit before the end, skip to the start of the ring
+            false => head
+        };
+
+        let end = start + size;
+        if end - state.tail > self.size {
+            return Ok(None);
+        }
+
+        let id = state.next_id;
+        state.next_id += 1;
+        state.head = end;
+        state.reservations.push_back(Reservation { id, end, thread: std::thread::current().id(), sync: None });
+
+        Ok(Some((id, start % self.size)))
+    }
+
+    // Blocks until the oldest reservation is free again. Callers must submit their own reservations first or this can deadlock
+    pub(crate) fn wait_for_space(&self) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            match state.reservations.front() {
+                None => return Ok(()),
+                Some(Reservation { sync: Some(sync), .. }) => {
+                    let sync = sync.clone();
+                    drop(state);
+
+                    return sync.wait();
+                },
+                // Another batch on this thread is holding the region and can't be submitted while we wait for it
+                Some(Reservation { thread, .. }) if *thread == std::thread::current().id() => {
+                    return Err(CobraError::InvalidUsage("The staging ring is full of another unsubmitted upload batch on this thread, submit it first".to_owned()));
+                },
+                // Another thread is still recording into the oldest region
+                Some(_) => state = self.submitted.wait(state).unwrap()
+            }
+        }
+    }
+
+    pub(crate) fn submit(&self, ids: &[u64], sync: &SyncPoint<Vulkan>) {
+        let mut state = self.state.lock().unwrap();
+        for reservation in state.reservations.iter_mut().filter(|reservation| ids.contains(&reservation.id)) {
+            reservation.sync = Some(sync.clone());
+        }
+
+        self.submitted.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_around_once_the_tail_moves() {
+        let ring = StagingRing::new(256);
+        let (first, offset) = ring.try_allocate(100).unwrap().unwrap();
+        assert_eq!(offset, 0);
+        let (_, offset) = ring.try_allocate(100).unwrap().unwrap();
+        assert_eq!(offset, 112); // aligned up from 100
+
+        // Doesn't fit before the end, and the start is still taken by the first reservation
+        assert!(ring.try_allocate(100).unwrap().is_none());
+
+        ring.submit(&[first], &SyncPoint::new());
+        let (_, offset) = ring.try_allocate(100).unwrap().unwrap();
+        assert_eq!(offset, 0);
+    }
+
+    #[test]
+    fn restarts_at_zero_once_empty() {
+        let ring = StagingRing::new(256);
+        let (id, _) = ring.try_allocate(200).unwrap().unwrap();
+        ring.submit(&[id], &SyncPoint::new());
+
+        // Would have to wrap if the head wasn't reset
+        let (_, offset) = ring.try_allocate(200).unwrap().unwrap();
+        assert_eq!(offset, 0);
+    }
+
+    #[test]
+    fn waiting_on_an_own_unsubmitted_reservation_fails() {
+        let ring = StagingRing::new(256);
+        let (id, _) = ring.try_allocate(256).unwrap().unwrap();
+        assert!(ring.try_allocate(16).unwrap().is_none());
+        assert!(matches!(ring.wait_for_space(), Err(CobraError::InvalidUsage(_))));
+
+        ring.submit(&[id], &SyncPoint::new());
+        assert!(ring.wait_for_space().is_ok());
+        assert!(ring.try_allocate(16).unwrap().is_some());
+    }
+
+    #[test]
+    fn waits_for_other_threads_to_submit() {
+        let ring = std::sync::Arc::new(StagingRing::new(256));
+        let (id, _) = ring.try_allocate(256).unwrap().unwrap();
+
+        let waiter = std::thread::spawn({
+            let ring = ring.clone();
+            move || ring.wait_for_space()
+        });
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        ring.submit(&[id], &SyncPoint::new());
+
+        assert!(waiter.join().unwrap().is_ok());
+    }
+}
@@ -2,7 +2,7 @@ use std::{ops::Deref, sync::RwLock};
 
 use ash::vk;
 
-use crate::{BlendFactor, BlendOp, CompareOperation, ImageFormat, ImageUsage};
+use crate::{BlendFactor, BlendOp, BufferUsage, CobraError, CompareOperation, ImageFormat, ImageUsage, PipelineStage};
 
 // Converters
 pub(crate) fn image_format_to_vulkan(format: ImageFormat) -> vk::Format {
@@ -17,6 +17,33 @@ pub(crate) fn image_format_to_vulkan(format: ImageFormat) -> vk::Format {
     }
  }
  
+ pub(crate) fn image_format_aspect(format: ImageFormat) -> vk::ImageAspectFlags {
+   match format {
+      ImageFormat::D32SFloat => vk::ImageAspectFlags::DEPTH,
+      _ => vk::ImageAspectFlags::COLOR
+   }
+ }
+
+ pub(crate) fn buffer_usage_to_vulkan(usages: BufferUsage) -> vk::BufferUsageFlags {
+   let mut ret = vk::BufferUsageFlags::empty();
+   for usage in usages {
+      ret |= match usage {
+         BufferUsage::TransferSrc => vk::BufferUsageFlags::TRANSFER_SRC,
+         BufferUsage::TransferDst => vk::BufferUsageFlags::TRANSFER_DST,
+         BufferUsage::Storage => vk::BufferUsageFlags::STORAGE_BUFFER,
+         BufferUsage::Index => vk::BufferUsageFlags::INDEX_BUFFER,
+         BufferUsage::Indirect => vk::BufferUsageFlags::INDIRECT_BUFFER,
+         BufferUsage::Vertex => vk::BufferUsageFlags::VERTEX_BUFFER,
+         BufferUsage::Uniform => vk::BufferUsageFlags::UNIFORM_BUFFER,
+         BufferUsage::AccelerationStructureInput => vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR,
+         BufferUsage::ShaderBindingTable => vk::BufferUsageFlags::SHADER_BINDING_TABLE_KHR,
+         _ => unreachable!()
+      };
+   }
+
+   ret
+ }
+
  pub(crate) fn image_usage_to_vulkan(usages: ImageUsage) -> vk::ImageUsageFlags {
    let mut ret = vk::ImageUsageFlags::empty();
    for usage in usages {
@@ -60,6 +87,49 @@ pub(crate) fn compare_op_to_vulkan(compare_op: CompareOperation) -> vk::CompareO
    }
 }
 
+pub(crate) fn pipeline_stage_to_vulkan(stages: PipelineStage) -> vk::PipelineStageFlags2 {
+   let mut ret = vk::PipelineStageFlags2::empty();
+   for stage in stages {
+      ret |= match stage {
+         PipelineStage::Compute => vk::PipelineStageFlags2::COMPUTE_SHADER,
+         PipelineStage::Transfer => vk::PipelineStageFlags2::ALL_TRANSFER,
+         PipelineStage::Graphics => vk::PipelineStageFlags2::ALL_GRAPHICS,
+         _ => unreachable!()
+      };
+   }
+
+   ret
+}
+
+// Error conversions
+impl From<vk::Result> for CobraError {
+   fn from(value: vk::Result) -> Self {
+      match value {
+         vk::Result::ERROR_OUT_OF_DEVICE_MEMORY => CobraError::OutOfDeviceMemory,
+         vk::Result::ERROR_OUT_OF_HOST_MEMORY => CobraError::OutOfHostMemory,
+         vk::Result::ERROR_DEVICE_LOST => CobraError::DeviceLost,
+         vk::Result::ERROR_SURFACE_LOST_KHR => CobraError::SurfaceLost,
+         vk::Result::ERROR_OUT_OF_DATE_KHR => CobraError::SwapchainOutOfDate,
+         vk::Result::ERROR_LAYER_NOT_PRESENT | vk::Result::ERROR_EXTENSION_NOT_PRESENT |
+         vk::Result::ERROR_FEATURE_NOT_PRESENT | vk::Result::ERROR_INCOMPATIBLE_DRIVER |
+         vk::Result::ERROR_FORMAT_NOT_SUPPORTED => CobraError::Unsupported(value.to_string()),
+         _ => CobraError::Backend(value.to_string())
+      }
+   }
+}
+
+impl From<ash::LoadingError> for CobraError {
+   fn from(value: ash::LoadingError) -> Self {
+      CobraError::Unsupported(format!("Failed to load the Vulkan library: {}", value))
+   }
+}
+
+impl From<spirv_cross2::SpirvCrossError> for CobraError {
+   fn from(value: spirv_cross2::SpirvCrossError) -> Self {
+      CobraError::ShaderCompile(value.to_string())
+   }
+}
+
 // Thread safe wrappers over pointer types
 pub(crate) struct AllocationInfo(RwLock<vk_mem::AllocationInfo>);
 
@@ -1,11 +1,11 @@
-use anyhow::Result;
 use ash::vk;
 use vk_mem::Alloc;
 use std::sync::Weak;
 use std::{ffi::c_void, sync::Arc};
 
-use crate::vulkan::internal_managers::utils::AllocationInfo;
-use crate::{BufferFlags, IBuffer, ICommandList, IQueue, Vulkan};
+use crate::vulkan::internal_managers::memory_report::ResourceMemoryType;
+use crate::vulkan::internal_managers::utils::{self, AllocationInfo};
+use crate::{BufferDesc, BufferFlags, BufferUsage, CobraError, DeviceFeatures, IBuffer, ICobra, IQueue, IUploadBatch, MapRead, MapWrite, MemoryPlacement, Result, SyncPoint, Vulkan};
 
 use super::queue::QueueVulkan;
 use super::CobraVulkan;
@@ -29,90 +29,147 @@ pub struct BufferVulkan {
     allocation_info: AllocationInfo,
     size: u64,
     address: u64,
+    flags: BufferFlags,
+    name: Option<String>,
 
     cobra: PtrType
 }
 
 impl IBuffer<Vulkan> for BufferVulkan {
-    fn set<T>(&mut self, queue: &mut QueueVulkan, source: &[T], buffer_offset: u64) -> Result<()> 
+    fn set<T>(&mut self, queue: &QueueVulkan, source: &[T], buffer_offset: u64) -> Result<SyncPoint<Vulkan>> 
         where T: Copy {
-        let cobra = self.cobra.get();
-        let staging_buffer = cobra.staging_buffer.read().unwrap();
-        let staging_buffer = staging_buffer.as_ref().unwrap();
-        staging_buffer.host_slice().copy_from_slice(source);
-        
-        let cmd = queue.begin()?;
-        cmd.copy_buffer_region(staging_buffer, self, source.len() as u64, 0, buffer_offset);
-        queue.submit(cmd, None)?.wait()?;
+        let mut upload = queue.begin_upload()?;
+        upload.write_buffer(self, source, buffer_offset)?;
+        upload.submit()
+    }
 
-        Ok(())
+    fn upload<T>(&mut self, queue: &QueueVulkan, source: &[T], buffer_offset: u64) -> Result<SyncPoint<Vulkan>>
+        where T: Copy {
+        if !self.is_host_visible() {
+            return self.set(queue, source, buffer_offset);
+        }
+
+        let size = std::mem::size_of_val(source) as u64;
+        if buffer_offset + size > self.size {
+            return Err(CobraError::InvalidUsage(format!("Tried to write {} bytes at offset {} into a buffer of {} bytes", size, buffer_offset, self.size)));
+        }
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(source.as_ptr().cast::<u8>(), self.host_address()?.cast::<u8>().add(buffer_offset as usize), size as usize);
+        }
+        self.flush(buffer_offset, size)?;
+
+        Ok(SyncPoint::new())
     }
 
-    fn host_address(&self) -> *mut c_void {
-        let address = self.allocation_info.read().unwrap().mapped_data;
-        assert!(!address.is_null());
+    fn is_host_visible(&self) -> bool {
+        !self.allocation_info.read().unwrap().mapped_data.is_null()
+    }
 
-        address
+    fn host_address(&self) -> Result<*mut c_void> {
+        let address = self.allocation_info.read().unwrap().mapped_data;
+        match address.is_null() {
+            true => Err(CobraError::InvalidUsage(format!("Buffer {} isn't host visible", self.name.as_deref().unwrap_or("(unnamed)")))),
+            false => Ok(address)
+        }
     }
 
     fn device_address(&self) -> u64 {
         self.address
     }
 
-    fn host_slice<T>(&self) -> &mut [T] {
+    fn host_slice<T>(&self) -> Result<&[T]>
+        where T: bytemuck::Pod {
         unsafe {
-            std::slice::from_raw_parts_mut(self.allocation_info.read().unwrap().mapped_data.cast(), self.size as usize / std::mem::size_of::<T>())
+            Ok(std::slice::from_raw_parts(self.host_address()?.cast(), self.len_of::<T>()))
         }
     }
 
     fn size(&self) -> u64 {
         self.size
     }
+
+    fn flush(&self, offset: u64, size: u64) -> Result<()> {
+        unsafe {
+            self.cobra.get().allocator.flush_allocation(&self.allocation.1, offset, size)?;
+        }
+
+        Ok(())
+    }
+
+    fn invalidate(&self, offset: u64, size: u64) -> Result<()> {
+        unsafe {
+            self.cobra.get().allocator.invalidate_allocation(&self.allocation.1, offset, size)?;
+        }
+
+        Ok(())
+    }
+
+    fn map_read<T>(&self) -> Result<MapRead<'_, T>>
+        where T: bytemuck::Pod {
+        self.invalidate(0, self.size)?;
+        Ok(MapRead::new(self.host_slice()?))
+    }
+
+    fn map_write<T>(&mut self) -> Result<MapWrite<'_, Vulkan, T>>
+        where T: bytemuck::Pod {
+        let data = unsafe { std::slice::from_raw_parts_mut(self.host_address()?.cast(), self.len_of::<T>()) };
+        Ok(MapWrite::new(&*self, data))
+    }
+
+    fn set_name(&mut self, name: &str) {
+        self.cobra.get().set_object_name(self.allocation.0, name);
+        self.name = Some(name.to_owned());
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
 }
 
 impl BufferVulkan {
-    pub(crate) fn new(cobra: Arc<CobraVulkan>, size: u64, flags: BufferFlags) -> Result<Self> {
-        Self::init(PtrType::Arc(cobra), size, flags)
+    fn len_of<T>(&self) -> usize {
+        (self.size as usize).checked_div(std::mem::size_of::<T>()).unwrap_or(0)
     }
 
-    pub(crate) fn new_weak(cobra: &Weak<CobraVulkan>, size: u64, flags: BufferFlags) -> Result<BufferVulkan> {
-        Self::init(PtrType::Weak(cobra.clone()), size, flags)
+    pub(crate) fn new(cobra: Arc<CobraVulkan>, desc: BufferDesc) -> Result<Self> {
+        Self::init(PtrType::Arc(cobra), desc)
     }
 
-    fn init(cobra: PtrType, size: u64, flags: BufferFlags) -> Result<BufferVulkan> {
+    pub(crate) fn new_weak(cobra: &Weak<CobraVulkan>, desc: BufferDesc) -> Result<BufferVulkan> {
+        Self::init(PtrType::Weak(cobra.clone()), desc)
+    }
+
+    fn init(cobra: PtrType, desc: BufferDesc) -> Result<BufferVulkan> {
         unsafe {
             let cb = cobra.get();
-            let mut allocation_info = vk_mem::AllocationCreateInfo::default();
-            allocation_info.usage = vk_mem::MemoryUsage::Auto;
+            let ray_tracing_usage = BufferUsage::AccelerationStructureInput | BufferUsage::ShaderBindingTable;
+            if desc.usage.intersects(ray_tracing_usage) && !cb.enabled_features.contains(DeviceFeatures::RayTracing) {
+                return Err(CobraError::Unsupported(format!("{:?} needs DeviceFeatures::RayTracing to be enabled", desc.usage & ray_tracing_usage)));
+            }
 
-            match flags {
-                BufferFlags::Default => {
-                    allocation_info.required_flags = vk::MemoryPropertyFlags::DEVICE_LOCAL;
-                },
-                BufferFlags::Upload | BufferFlags::Readback => {
-                    allocation_info.flags = vk_mem::AllocationCreateFlags::MAPPED | vk_mem::AllocationCreateFlags::HOST_ACCESS_RANDOM;
-                    allocation_info.required_flags = vk::MemoryPropertyFlags::HOST_COHERENT;
+            let buffer_info = vk::BufferCreateInfo::default()
+                .size(desc.size)
+                .usage(utils::buffer_usage_to_vulkan(desc.usage) | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS);
+
+            let rebar = cb.supports_resizable_bar();
+            let (buffer, allocation) = match cb.allocator.create_buffer(&buffer_info, &allocation_create_info(desc, rebar)) {
+                // Even with resizable bar the host visible heap can run out, the buffer just gets written through staging then
+                Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY) if rebar && desc.placement == MemoryPlacement::DeviceUpload => {
+                    cb.allocator.create_buffer(&buffer_info, &allocation_create_info(desc, false))?
                 },
-                BufferFlags::DeviceUpload => {
-                    allocation_info.flags = vk_mem::AllocationCreateFlags::MAPPED | vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE;
-                    allocation_info.required_flags = vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::HOST_COHERENT;
-                }
-            }
-            
-            let (buffer, allocation) = cb.allocator.create_buffer(&vk::BufferCreateInfo::default()
-                .size(size)
-                .usage(
-                    vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST |
-                    vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS |
-                    vk::BufferUsageFlags::INDEX_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER 
-                )
-            , &allocation_info)?;
+                result => result?
+            };
            let allocation_info = AllocationInfo::new(cb.allocator.get_allocation_info(&allocation));
            let address = cb.device.get_buffer_device_address(&vk::BufferDeviceAddressInfo::default().buffer(buffer));
+           let flags = BufferFlags::from(desc.placement);
+           cb.track_allocation(ResourceMemoryType::Buffer(flags), allocation_info.read().unwrap().size);
 
             drop(cb);
             Ok(BufferVulkan {
-                allocation: (buffer, allocation), allocation_info, address, size,
+                allocation: (buffer, allocation), allocation_info, address, flags,
+                size: desc.size,
+                name: None,
                 cobra
             })
         }
@@ -121,6 +178,46 @@ impl BufferVulkan {
 
 impl Drop for BufferVulkan {
     fn drop(&mut self) {
-        self.cobra.get().push(self.allocation);
+        let cobra = self.cobra.get();
+        cobra.untrack_allocation(ResourceMemoryType::Buffer(self.flags), self.allocation_info.read().unwrap().size);
+        cobra.push(self.allocation);
+    }
+}
+
+fn allocation_create_info(desc: BufferDesc, rebar: bool) -> vk_mem::AllocationCreateInfo {
+    let mut allocation_info = vk_mem::AllocationCreateInfo::default();
+    match desc.placement {
+        MemoryPlacement::DeviceUpload if rebar => {
+            allocation_info.usage = vk_mem::MemoryUsage::AutoPreferDevice;
+            allocation_info.flags = vk_mem::AllocationCreateFlags::MAPPED | vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE;
+            allocation_info.required_flags = vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+        },
+        MemoryPlacement::GpuOnly | MemoryPlacement::DeviceUpload => {
+            allocation_info.usage = vk_mem::MemoryUsage::AutoPreferDevice;
+            allocation_info.required_flags = vk::MemoryPropertyFlags::DEVICE_LOCAL;
+        },
+        MemoryPlacement::CpuToGpu => {
+            allocation_info.usage = vk_mem::MemoryUsage::AutoPreferHost;
+            allocation_info.flags = vk_mem::AllocationCreateFlags::MAPPED | vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE;
+            allocation_info.required_flags = vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+        },
+        MemoryPlacement::GpuToCpu => {
+            // Reads from uncached memory are extremely slow, which is the whole point of a readback buffer
+            allocation_info.usage = vk_mem::MemoryUsage::AutoPreferHost;
+            allocation_info.flags = vk_mem::AllocationCreateFlags::MAPPED | vk_mem::AllocationCreateFlags::HOST_ACCESS_RANDOM;
+            allocation_info.required_flags = vk::MemoryPropertyFlags::HOST_VISIBLE;
+            allocation_info.preferred_flags = vk::MemoryPropertyFlags::HOST_CACHED;
+        },
+        MemoryPlacement::PreferRebar => {
+            allocation_info.usage = vk_mem::MemoryUsage::Auto;
+            allocation_info.flags = vk_mem::AllocationCreateFlags::MAPPED | vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE;
+            allocation_info.required_flags = vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+            allocation_info.preferred_flags = vk::MemoryPropertyFlags::DEVICE_LOCAL;
+        }
+    }
+    if desc.dedicated {
+        allocation_info.flags |= vk_mem::AllocationCreateFlags::DEDICATED_MEMORY;
     }
+
+    allocation_info
 }
\ No newline at end of file
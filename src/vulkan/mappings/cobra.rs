@@ -1,18 +1,23 @@
-use anyhow::Result;
 use ash::vk;
 use glam::UVec2;
-use std::ffi::c_void;
+use raw_window_handle::{HasDisplayHandle, HasWindowHandle, RawDisplayHandle};
+use std::ffi::CStr;
 use std::mem::ManuallyDrop;
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 
-use crate::vulkan::internal_managers::deletion_queue::DeleteValue;
+use crate::vulkan::internal_managers::debug_utils::{DebugMessenger, VALIDATION_LAYER_NAME};
+use crate::vulkan::internal_managers::deletion_queue::{DeletionQueue, QUEUE_COUNT};
+use crate::vulkan::internal_managers::memory_report::ResourceMemory;
 use crate::vulkan::internal_managers::pipeline_manager::GraphicsPipelineKey;
+use crate::vulkan::internal_managers::readback_pool::ReadbackPool;
 use crate::vulkan::internal_managers::resource_handle::ResourceType;
-use crate::{Buffer, BufferFlags, ICobra, ImageFormat, ImageUsage, QueueType, Vulkan};
+use crate::vulkan::internal_managers::staging_ring::StagingRing;
+use crate::{AdapterInfo, AdapterSelection, AdapterType, Buffer, BufferDesc, BufferUsage, CobraDesc, CobraError, DeviceCapabilities, DeviceFeatures, ICobra, ImageFormat, ImageUsage, MemoryHeapInfo, MemoryPlacement, MemoryReport, QueueType, Result, Vulkan, WaitMode};
 
 use super::buffer::BufferVulkan;
+use super::fence::FenceVulkan;
 use super::queue::QueueVulkan;
 use super::swapchain::SwapchainVulkan;
 use super::{ImageVulkan, SamplerVulkan};
@@ -21,111 +26,174 @@ pub(crate) const SAMPLER_BINDING: u32 = 0;
 pub(crate) const STORAGE_IMAGE_BINDING: u32 = 1;
 pub(crate) const SAMPLED_IMAGE_BINDING: u32 = 2;
 
+// Entry, instance, messenger, whether debug utils is enabled and the surface extensions that were enabled
+type InstanceParts = (ash::Entry, ash::Instance, Option<DebugMessenger>, bool, Vec<&'static CStr>);
+
 pub(crate) struct IDInfo {
     pub id_counter: u32,
     pub recycled_ids: Vec<u32>
 }
 
+pub(crate) struct WsiVulkan {
+    pub(crate) surface_fn: ash::khr::surface::Instance,
+    pub(crate) swapchain_device_fn: ash::khr::swapchain::Device,
+    #[cfg(target_os = "windows")]
+    pub(crate) win32_surface_fn: Option<ash::khr::win32_surface::Instance>,
+    #[cfg(target_os = "linux")]
+    pub(crate) xlib_surface_fn: Option<ash::khr::xlib_surface::Instance>,
+    #[cfg(target_os = "linux")]
+    pub(crate) xcb_surface_fn: Option<ash::khr::xcb_surface::Instance>,
+    #[cfg(target_os = "linux")]
+    pub(crate) wayland_surface_fn: Option<ash::khr::wayland_surface::Instance>
+}
+
+impl WsiVulkan {
+    fn new(entry: &ash::Entry, instance: &ash::Instance, device: &ash::Device, surface_extensions: &[&CStr]) -> WsiVulkan {
+        WsiVulkan {
+            surface_fn: ash::khr::surface::Instance::new(entry, instance),
+            swapchain_device_fn: ash::khr::swapchain::Device::new(instance, device),
+            #[cfg(target_os = "windows")]
+            win32_surface_fn: surface_extensions.contains(&ash::khr::win32_surface::NAME).then(|| ash::khr::win32_surface::Instance::new(entry, instance)),
+            #[cfg(target_os = "linux")]
+            xlib_surface_fn: surface_extensions.contains(&ash::khr::xlib_surface::NAME).then(|| ash::khr::xlib_surface::Instance::new(entry, instance)),
+            #[cfg(target_os = "linux")]
+            xcb_surface_fn: surface_extensions.contains(&ash::khr::xcb_surface::NAME).then(|| ash::khr::xcb_surface::Instance::new(entry, instance)),
+            #[cfg(target_os = "linux")]
+            wayland_surface_fn: surface_extensions.contains(&ash::khr::wayland_surface::NAME).then(|| ash::khr::wayland_surface::Instance::new(entry, instance))
+        }
+    }
+}
+
+struct QueueFamilies {
+    graphics: u32,
+    compute: Option<u32>, // None when there's no dedicated family
+    transfer: Option<u32>
+}
+
 pub struct CobraVulkan {
-    pub(crate) deletion_queue: Mutex<Vec<DeleteValue>>,
+    pub(crate) deletion_queue: Mutex<DeletionQueue>,
 
     pub(crate) _entry: ash::Entry,
     pub(crate) instance: ash::Instance,
+    pub(crate) debug_messenger: Option<DebugMessenger>,
+    pub(crate) debug_utils_fn: Option<ash::ext::debug_utils::Device>,
     pub(crate) chosen_gpu: vk::PhysicalDevice,
+    pub(crate) adapter: AdapterInfo,
     pub(crate) device: ash::Device,
 
     pub(crate) allocator: ManuallyDrop<vk_mem::Allocator>,
-    pub(crate) timeline_value: AtomicU64,
     pub(crate) graphics_queue: QueueVulkan,
+    pub(crate) compute_queue: Option<QueueVulkan>,
+    pub(crate) transfer_queue: Option<QueueVulkan>,
 
     pub(crate) bindless_pool: vk::DescriptorPool,
     pub(crate) bindless_set_layout: vk::DescriptorSetLayout,
     pub(crate) bindless_set: vk::DescriptorSet,
     pub(crate) bindless_pipeline_layout: vk::PipelineLayout,
 
-    pub(crate) surface_fn: ash::khr::surface::Instance,
-    pub(crate) swapchain_device_fn: ash::khr::swapchain::Device,
-    #[cfg(target_os = "windows")]
-    pub(crate) platform_surface_fn: ash::khr::win32_surface::Instance,
-    #[cfg(target_os = "linux")]
-    pub(crate) platform_surface_fn: ash::khr::wayland_surface::Instance,
+    pub(crate) wsi: Option<WsiVulkan>, // None when the context is headless
 
     pub(crate) graphics_pipelines: RwLock<HashMap<GraphicsPipelineKey, vk::Pipeline>>,
     pub(crate) id_infos: Mutex<HashMap<ResourceType, IDInfo>>,
+    pub(crate) resource_memory: Mutex<ResourceMemory>,
 
     // The only point of ManuallyDrop here is to inhibit the destructor on this buffer, since the Arc for Cobra will be dead when it tries to be deleted so we have to do it manually
     pub(crate) staging_buffer: RwLock<Option<ManuallyDrop<BufferVulkan>>>,
-    pub(crate) resizable_bar: bool
+    pub(crate) staging_ring: StagingRing,
+    pub(crate) readback_pool: Mutex<ReadbackPool>,
+    pub(crate) capabilities: DeviceCapabilities,
+    pub(crate) enabled_features: DeviceFeatures,
+    pub(crate) push_constant_size: u32
 }
 
 impl ICobra<Vulkan> for CobraVulkan {
-    fn new() -> Result<Arc<Self>> {
+    fn new_with_desc(desc: CobraDesc) -> Result<Arc<Self>> {
         unsafe {
-            let (entry, instance) = Self::create_instance()?;
-            let chosen_gpu = Self::pick_gpu(&instance)?;
-            let (device, graphics_queue) = Self::create_device_and_queues(&instance, &chosen_gpu)?;
-            let (bindless_pool, bindless_set_layout, bindless_set, bindless_pipeline_layout) = Self::setup_bindless(&device)?;
-            
-            let surface_fn = ash::khr::surface::Instance::new(&entry, &instance);
-            let swapchain_device_fn = ash::khr::swapchain::Device::new(&instance, &device);
-            #[cfg(target_os = "windows")]
-            let platform_surface_fn = ash::khr::win32_surface::Instance::new(&entry, &instance);
-            #[cfg(target_os = "linux")]
-            let platform_surface_fn = ash::khr::wayland_surface::Instance::new(&entry, &instance);
-            
+            if desc.api_version < (1, 3) {
+                return Err(CobraError::Unsupported(format!("Vulkan 1.3 is the minimum supported api version, got {}.{}", desc.api_version.0, desc.api_version.1)));
+            }
+
+            let (entry, instance, debug_messenger, debug_utils, surface_extensions) = Self::create_instance(&desc)?;
+            let mut guard = InitGuard { instance: Some(instance.clone()), debug_messenger, device: None, bindless: None };
+            let (chosen_gpu, adapter, capabilities) = Self::pick_and_validate(&instance, &desc)?;
+
+            // Memory budget only feeds the allocator better numbers, so it's always enabled when available
+            let enabled_features = desc.features | (capabilities.features & DeviceFeatures::MemoryBudget);
+            let (device, queue_families) = Self::create_device_and_queues(&instance, &chosen_gpu, desc.headless, enabled_features)?;
+            guard.device = Some(device.clone());
+            let (bindless_pool, bindless_set_layout, bindless_set, bindless_pipeline_layout) = Self::setup_bindless(&device, &desc)?;
+            guard.bindless = Some((bindless_pool, bindless_set_layout, bindless_pipeline_layout));
+            let debug_utils_fn = debug_utils.then(|| ash::ext::debug_utils::Device::new(&instance, &device));
+
+            let wsi = match desc.headless {
+                true => None,
+                false => Some(WsiVulkan::new(&entry, &instance, &device, &surface_extensions))
+            };
+
             let mut allocator_info = vk_mem::AllocatorCreateInfo::new(&instance, &device, chosen_gpu);
+            allocator_info.vulkan_api_version = api_version(&desc);
             allocator_info.flags = vk_mem::AllocatorCreateFlags::BUFFER_DEVICE_ADDRESS;
+            if enabled_features.contains(DeviceFeatures::MemoryBudget) {
+                allocator_info.flags |= vk_mem::AllocatorCreateFlags::EXT_MEMORY_BUDGET;
+            }
             let allocator = vk_mem::Allocator::new(allocator_info)?;
- 
-            let resizable_bar = {
-                let memory_properties = instance.get_physical_device_memory_properties(chosen_gpu);
-
-                let mut max_device_memory = 0;
-                for heap in memory_properties.memory_heaps_as_slice() {
-                    if heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL) {
-                        max_device_memory = std::cmp::max(max_device_memory, heap.size);
-                    }
-                }
-
-                let mut max_host_visible_device_memory = 0;
-                for ty in memory_properties.memory_types_as_slice() {
-                    if ty.property_flags.contains(vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::HOST_VISIBLE) {
-                        max_host_visible_device_memory = std::cmp::max(max_host_visible_device_memory, memory_properties.memory_heaps[ty.heap_index as usize].size);
-                    }
-                }
-
-                max_device_memory == max_host_visible_device_memory
-            };
+            let debug_messenger = guard.release();
 
+            // From here on dropping the context cleans up, it copes with queues and the staging buffer not being set up yet
             let ret = Arc::new(CobraVulkan {
-                deletion_queue: Mutex::new(Vec::new()),
+                deletion_queue: Mutex::new(DeletionQueue::default()),
                 _entry: entry,
-                instance, chosen_gpu, device,
+                instance, debug_messenger, debug_utils_fn, chosen_gpu, adapter, device,
 
                 allocator: ManuallyDrop::new(allocator),
-                timeline_value: AtomicU64::new(0),
-                graphics_queue: QueueVulkan::new(),
+                graphics_queue: QueueVulkan::new(QueueType::Graphics),
+                compute_queue: queue_families.compute.map(|_| QueueVulkan::new(QueueType::Compute)),
+                transfer_queue: queue_families.transfer.map(|_| QueueVulkan::new(QueueType::Transfer)),
 
                 bindless_pool, bindless_set_layout, bindless_set, bindless_pipeline_layout,
 
-                surface_fn, swapchain_device_fn, platform_surface_fn,
+                wsi,
 
                 graphics_pipelines: RwLock::new(HashMap::new()),
                 id_infos: Mutex::new(HashMap::new()),
+                resource_memory: Mutex::new(ResourceMemory::default()),
 
                 staging_buffer: RwLock::new(None),
-                resizable_bar
+                staging_ring: StagingRing::new(desc.staging_buffer_size),
+                readback_pool: Mutex::new(ReadbackPool::default()),
+                capabilities,
+                enabled_features,
+                push_constant_size: desc.push_constant_size
             });
             let ptr = Arc::as_ptr(&ret) as *mut CobraVulkan;
-            (*ptr).graphics_queue.init(ptr, graphics_queue.0, graphics_queue.1)?;
+            (*ptr).graphics_queue.init(&ret, ret.device.get_device_queue(queue_families.graphics, 0), queue_families.graphics)?;
+            if let (Some(queue), Some(family)) = (&mut (*ptr).compute_queue, queue_families.compute) {
+                queue.init(&ret, ret.device.get_device_queue(family, 0), family)?;
+            }
+            if let (Some(queue), Some(family)) = (&mut (*ptr).transfer_queue, queue_families.transfer) {
+                queue.init(&ret, ret.device.get_device_queue(family, 0), family)?;
+            }
 
-            ret.staging_buffer.write().unwrap().replace(ManuallyDrop::new(BufferVulkan::new_weak(&Arc::downgrade(&ret), 64 * 1024 * 1024, BufferFlags::Upload)?));
+            ret.staging_buffer.write().unwrap().replace(ManuallyDrop::new(BufferVulkan::new_weak(&Arc::downgrade(&ret), BufferDesc::new(desc.staging_buffer_size)
+                .usage(BufferUsage::TransferSrc)
+                .placement(MemoryPlacement::CpuToGpu)
+            )?));
             Ok(ret)
         }
     }
 
-    fn new_buffer(&self, cobra: Arc<Self>, size: u64, flags: BufferFlags) -> Result<Buffer<Vulkan>> {
-        BufferVulkan::new(cobra, size, flags)
+    fn enumerate_adapters() -> Result<Vec<AdapterInfo>> {
+        unsafe {
+            let (_entry, instance, _, _, _) = Self::create_instance(&CobraDesc::default().headless(true))?;
+            let adapters = Self::enumerate_adapters_from_instance(&instance);
+            instance.destroy_instance(None);
+
+            Ok(adapters?.into_iter().map(|adapter| adapter.1).collect())
+        }
+    }
+
+    fn new_buffer_with_desc(&self, cobra: Arc<Self>, desc: BufferDesc) -> Result<Buffer<Vulkan>> {
+        BufferVulkan::new(cobra, desc)
     }
 
     fn new_image(&self, cobra: Arc<Self>, size: impl Into<UVec2>, format: ImageFormat, usage: ImageUsage) -> Result<ImageVulkan> {
@@ -136,80 +204,279 @@ impl ICobra<Vulkan> for CobraVulkan {
         SamplerVulkan::new(cobra)
     }
 
-    fn new_swapchain(&self, cobra: Arc<Self>, window: *mut c_void, size: UVec2) -> Result<SwapchainVulkan> {
+    fn new_swapchain(&self, cobra: Arc<Self>, window: &(impl HasWindowHandle + HasDisplayHandle), size: UVec2) -> Result<SwapchainVulkan> {
+        if self.wsi.is_none() {
+            return Err(CobraError::InvalidUsage("Tried to create a swapchain on a headless context".to_owned()));
+        }
+
         SwapchainVulkan::new(cobra, window, size)
     }
 
+    fn new_fence(&self, cobra: Arc<Self>) -> Result<Arc<FenceVulkan>> {
+        Ok(Arc::new(FenceVulkan::new_user(cobra)?))
+    }
+
+    fn wait_for_fences(&self, fences: &[(&FenceVulkan, u64)], mode: WaitMode, timeout: Duration) -> Result<bool> {
+        unsafe {
+            let semaphores: Vec<vk::Semaphore> = fences.iter().map(|(fence, _)| fence.timeline_semaphore).collect();
+            let values: Vec<u64> = fences.iter().map(|(_, value)| *value).collect();
+
+            let result = self.device.wait_semaphores(&vk::SemaphoreWaitInfo::default()
+                .flags(match mode {
+                    WaitMode::Any => vk::SemaphoreWaitFlags::ANY,
+                    WaitMode::All => vk::SemaphoreWaitFlags::empty()
+                })
+                .semaphores(&semaphores)
+                .values(&values)
+            , u64::try_from(timeout.as_nanos()).unwrap_or(u64::MAX));
+
+            match result {
+                Ok(()) => (),
+                Err(vk::Result::TIMEOUT) => return Ok(false),
+                Err(err) => return Err(err.into())
+            }
+
+            // With any we don't know which fence woke us up, current_value will find out when asked
+            if mode == WaitMode::All {
+                for (fence, value) in fences {
+                    fence.mark_reached(*value);
+                }
+            }
+
+            Ok(true)
+        }
+    }
+
     fn queue(&self, ty: QueueType) -> &QueueVulkan {
         match ty {
-            QueueType::Graphics => &self.graphics_queue
+            QueueType::Graphics => &self.graphics_queue,
+            QueueType::Compute => self.compute_queue.as_ref().unwrap_or(&self.graphics_queue),
+            QueueType::Transfer => self.transfer_queue.as_ref().or(self.compute_queue.as_ref()).unwrap_or(&self.graphics_queue)
         }
     }
 
+    fn adapter(&self) -> &AdapterInfo {
+        &self.adapter
+    }
+
+    fn capabilities(&self) -> &DeviceCapabilities {
+        &self.capabilities
+    }
+
     fn supports_resizable_bar(&self) -> bool {
-        self.resizable_bar
+        self.capabilities.supports(DeviceFeatures::ResizableBar)
+    }
+
+    fn memory_report(&self) -> Result<MemoryReport> {
+        self.query_memory_report()
     }
 }
 
 impl CobraVulkan {
 
-    pub fn advance(&self) -> u64 {
-        self.timeline_value.fetch_add(1, Ordering::SeqCst) + 1
+    pub(crate) fn queues(&self) -> [&QueueVulkan; QUEUE_COUNT] {
+        [self.queue(QueueType::Graphics), self.queue(QueueType::Compute), self.queue(QueueType::Transfer)]
+    }
+
+    pub(crate) fn queue_family(&self, ty: QueueType) -> u32 {
+        self.queue(ty).queue_family
+    }
+
+    pub(crate) fn wsi(&self) -> &WsiVulkan {
+        self.wsi.as_ref().expect("Surface functions are unavailable on a headless context")
     }
 
-    fn create_instance() -> Result<(ash::Entry, ash::Instance)> {
+    // Also returns whether debug utils got enabled, names and labels work with it even without a messenger
+    fn create_instance(desc: &CobraDesc) -> Result<InstanceParts> {
         unsafe {
             let entry = ash::Entry::load()?;
-            let mut extensions = vec![vk::KHR_SURFACE_NAME.as_ptr()];
-            extensions.append(&mut platform_surface_extensions());
+            let surface_extensions = match desc.headless {
+                true => Vec::new(),
+                false => platform_surface_extensions(&entry, desc.display)?
+            };
+
+            let mut extensions = Vec::new();
+            if !desc.headless {
+                extensions.push(vk::KHR_SURFACE_NAME.as_ptr());
+                extensions.extend(surface_extensions.iter().map(|extension| extension.as_ptr()));
+            }
+
+            // Enabled whenever it's there so capture tools like RenderDoc see object names and labels outside of debug mode too
+            let available_extensions = entry.enumerate_instance_extension_properties(None)?;
+            let debug_utils = available_extensions.iter().any(|extension| extension.extension_name_as_c_str() == Ok(ash::ext::debug_utils::NAME));
+            if debug_utils {
+                extensions.push(ash::ext::debug_utils::NAME.as_ptr());
+            }
+
+            let mut layers = Vec::new();
+            if let Some(debug) = &desc.debug {
+                if debug.validation {
+                    let available_layers = entry.enumerate_instance_layer_properties()?;
+                    match available_layers.iter().any(|layer| layer.layer_name_as_c_str() == Ok(VALIDATION_LAYER_NAME)) {
+                        true => layers.push(VALIDATION_LAYER_NAME.as_ptr()),
+                        false => log::warn!(target: "cobra", "Validation was requested but {:?} is not installed", VALIDATION_LAYER_NAME)
+                    }
+                }
+
+                if !debug_utils {
+                    log::warn!(target: "cobra", "Debug mode was requested but {:?} is not available", ash::ext::debug_utils::NAME);
+                }
+            }
 
             let instance = entry.create_instance(&vk::InstanceCreateInfo::default()
                 .application_info(&vk::ApplicationInfo::default()
-                    .api_version(vk::API_VERSION_1_3)
+                    .api_version(api_version(desc))
                 )
+                .enabled_layer_names(&layers)
                 .enabled_extension_names(&extensions)
             , None)?;
 
-            Ok((entry, instance))
+            let debug_messenger = match (&desc.debug, debug_utils) {
+                (Some(debug), true) => match DebugMessenger::new(&entry, &instance, debug) {
+                    Ok(debug_messenger) => Some(debug_messenger),
+                    Err(err) => {
+                        instance.destroy_instance(None);
+                        return Err(err);
+                    }
+                },
+                _ => None
+            };
+
+            Ok((entry, instance, debug_messenger, debug_utils, surface_extensions))
         }
     }
 
-    fn pick_gpu(instance: &ash::Instance) -> Result<vk::PhysicalDevice> {
+    fn enumerate_adapters_from_instance(instance: &ash::Instance) -> Result<Vec<(vk::PhysicalDevice, AdapterInfo)>> {
         unsafe {
             let physical_devices = instance.enumerate_physical_devices()?;
 
-            let mut chosen_gpu = physical_devices[0];
-            for physical_device in physical_devices {
+            let mut adapters = Vec::new();
+            for (index, physical_device) in physical_devices.into_iter().enumerate() {
                 let properties = instance.get_physical_device_properties(physical_device);
-                if properties.device_type == vk::PhysicalDeviceType::DISCRETE_GPU {
-                    chosen_gpu = physical_device;
-                    break;
-                }
+                let memory_properties = instance.get_physical_device_memory_properties(physical_device);
+
+                adapters.push((physical_device, AdapterInfo {
+                    index,
+                    name: properties.device_name_as_c_str().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+                    vendor_id: properties.vendor_id,
+                    device_id: properties.device_id,
+                    ty: match properties.device_type {
+                        vk::PhysicalDeviceType::INTEGRATED_GPU => AdapterType::IntegratedGpu,
+                        vk::PhysicalDeviceType::DISCRETE_GPU => AdapterType::DiscreteGpu,
+                        vk::PhysicalDeviceType::VIRTUAL_GPU => AdapterType::VirtualGpu,
+                        vk::PhysicalDeviceType::CPU => AdapterType::Cpu,
+                        _ => AdapterType::Other
+                    },
+                    driver_version: properties.driver_version,
+                    memory_heaps: memory_properties.memory_heaps_as_slice().iter().map(|heap| MemoryHeapInfo {
+                        size: heap.size,
+                        device_local: heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL)
+                    }).collect()
+                }));
             }
 
-            Ok(chosen_gpu)
+            Ok(adapters)
         }
     }
 
-    fn create_device_and_queues(instance: &ash::Instance, chosen_gpu: &vk::PhysicalDevice) -> Result<(ash::Device, (vk::Queue, u32))> {
+    fn pick_and_validate(instance: &ash::Instance, desc: &CobraDesc) -> Result<(vk::PhysicalDevice, AdapterInfo, DeviceCapabilities)> {
         unsafe {
-            let mut graphics_queue_family: u32 = 0;
+            let (chosen_gpu, adapter) = Self::pick_gpu(instance, &desc.adapter)?;
+            let capabilities = Self::query_capabilities(instance, chosen_gpu)?;
+
+            let device_api_version = instance.get_physical_device_properties(chosen_gpu).api_version;
+            if device_api_version < api_version(desc) {
+                return Err(CobraError::Unsupported(format!("{} only supports Vulkan {}.{} but {}.{} was requested", adapter.name,
+                    vk::api_version_major(device_api_version), vk::api_version_minor(device_api_version), desc.api_version.0, desc.api_version.1
+                )));
+            }
+            desc.validate(&capabilities)?;
+
+            Ok((chosen_gpu, adapter, capabilities))
+        }
+    }
+
+    fn pick_gpu(instance: &ash::Instance, selection: &AdapterSelection) -> Result<(vk::PhysicalDevice, AdapterInfo)> {
+        let mut adapters = Self::enumerate_adapters_from_instance(instance)?;
+        if adapters.is_empty() {
+            return Err(CobraError::Unsupported("No Vulkan capable adapters were found".to_owned()));
+        }
 
+        let infos: Vec<AdapterInfo> = adapters.iter().map(|adapter| adapter.1.clone()).collect();
+        match selection.select(&infos) {
+            Some(index) => Ok(adapters.swap_remove(index)),
+            None => Err(CobraError::Unsupported(format!("No adapter matches {:?}, available adapters: {:?}", selection, infos.iter().map(|adapter| &adapter.name).collect::<Vec<_>>())))
+        }
+    }
+
+    fn create_device_and_queues(instance: &ash::Instance, chosen_gpu: &vk::PhysicalDevice, headless: bool, features: DeviceFeatures) -> Result<(ash::Device, QueueFamilies)> {
+        unsafe {
             let queue_families = instance.get_physical_device_queue_family_properties(*chosen_gpu);
-            for (i, &queue_family) in queue_families.iter().enumerate() {
-                if queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
-                    graphics_queue_family = i as u32;
-                }
-            }
+            let find_family = |required: vk::QueueFlags, excluded: vk::QueueFlags| queue_families.iter()
+                .position(|family| family.queue_flags.contains(required) && !family.queue_flags.intersects(excluded))
+                .map(|i| i as u32);
+
+            let families = QueueFamilies {
+                graphics: find_family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE, vk::QueueFlags::empty())
+                    .ok_or_else(|| CobraError::Unsupported("The device has no queue family supporting both graphics and compute".to_owned()))?,
+                compute: find_family(vk::QueueFlags::COMPUTE, vk::QueueFlags::GRAPHICS),
+                transfer: find_family(vk::QueueFlags::TRANSFER, vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+            };
 
-            // TODO: only using GPL for renderdoc support with shader module deprecation
-            let extensions = [ash::khr::swapchain::NAME.as_ptr(), ash::ext::graphics_pipeline_library::NAME.as_ptr()];
-            let device = instance.create_device(*chosen_gpu, &vk::DeviceCreateInfo::default()
-                .queue_create_infos(&[vk::DeviceQueueCreateInfo::default()
-                    .queue_family_index(graphics_queue_family)
+            let queue_create_infos: Vec<vk::DeviceQueueCreateInfo> = [Some(families.graphics), families.compute, families.transfer]
+                .into_iter()
+                .flatten()
+                .map(|family| vk::DeviceQueueCreateInfo::default()
+                    .queue_family_index(family)
                     .queue_priorities(&[1.0])
-                ])
+                )
+                .collect();
+
+            // TODO: only using GPL for renderdoc support with shader module deprecation
+            let mut extensions = vec![ash::ext::graphics_pipeline_library::NAME.as_ptr()];
+            if !headless {
+                extensions.push(ash::khr::swapchain::NAME.as_ptr());
+            }
+            if features.contains(DeviceFeatures::MemoryBudget) {
+                extensions.push(ash::ext::memory_budget::NAME.as_ptr());
+            }
+            if features.contains(DeviceFeatures::MeshShader) {
+                extensions.push(ash::ext::mesh_shader::NAME.as_ptr());
+            }
+            if features.contains(DeviceFeatures::RayTracing) {
+                extensions.push(ash::khr::ray_tracing_pipeline::NAME.as_ptr());
+                extensions.push(ash::khr::acceleration_structure::NAME.as_ptr());
+                extensions.push(ash::khr::deferred_host_operations::NAME.as_ptr());
+            }
+
+            let mut mesh_shader_features = vk::PhysicalDeviceMeshShaderFeaturesEXT::default()
+                .mesh_shader(true)
+                .task_shader(true);
+            let mut ray_tracing_features = vk::PhysicalDeviceRayTracingPipelineFeaturesKHR::default()
+                .ray_tracing_pipeline(true);
+            let mut acceleration_structure_features = vk::PhysicalDeviceAccelerationStructureFeaturesKHR::default()
+                .acceleration_structure(true);
+
+            let mut device_info = vk::DeviceCreateInfo::default();
+            if features.contains(DeviceFeatures::MeshShader) {
+                device_info = device_info.push_next(&mut mesh_shader_features);
+            }
+            if features.contains(DeviceFeatures::RayTracing) {
+                device_info = device_info
+                    .push_next(&mut ray_tracing_features)
+                    .push_next(&mut acceleration_structure_features);
+            }
+
+            let device = instance.create_device(*chosen_gpu, &device_info
+                .queue_create_infos(&queue_create_infos)
                 .enabled_extension_names(&extensions)
+                .enabled_features(&vk::PhysicalDeviceFeatures::default()
+                    .shader_int64(features.contains(DeviceFeatures::ShaderInt64))
+                    .shader_int16(features.contains(DeviceFeatures::ShaderInt16))
+                    .shader_float64(features.contains(DeviceFeatures::ShaderFloat64))
+                    .multi_draw_indirect(features.contains(DeviceFeatures::MultiDrawIndirect))
+                    .sampler_anisotropy(features.contains(DeviceFeatures::SamplerAnisotropy))
+                )
                 .push_next(&mut vk::PhysicalDeviceVulkan11Features::default()
                     .variable_pointers(true)
                     .variable_pointers_storage_buffer(true)
@@ -222,6 +489,9 @@ impl CobraVulkan {
                     .scalar_block_layout(true)
                     .timeline_semaphore(true)
                     .buffer_device_address(true)
+                    .shader_int8(features.contains(DeviceFeatures::ShaderInt8))
+                    .shader_float16(features.contains(DeviceFeatures::ShaderFloat16))
+                    .draw_indirect_count(features.contains(DeviceFeatures::DrawIndirectCount))
                 )
                 .push_next(&mut vk::PhysicalDeviceVulkan13Features::default()
                     .synchronization2(true)
@@ -232,25 +502,23 @@ impl CobraVulkan {
                 )
             , None)?;
 
-            let graphics_queue = device.get_device_queue(graphics_queue_family, 0);
-
-            Ok((device, (graphics_queue, graphics_queue_family)))
+            Ok((device, families))
         }
     }
 
-    fn setup_bindless(device: &ash::Device) -> Result<(vk::DescriptorPool, vk::DescriptorSetLayout, vk::DescriptorSet, vk::PipelineLayout)> {
+    fn setup_bindless(device: &ash::Device, desc: &CobraDesc) -> Result<(vk::DescriptorPool, vk::DescriptorSetLayout, vk::DescriptorSet, vk::PipelineLayout)> {
         unsafe {
-            const BINDING_INFOS: [(vk::DescriptorType, u32, u32); 3] = [
-                (vk::DescriptorType::SAMPLER, 1 << 12, SAMPLER_BINDING),
-                (vk::DescriptorType::STORAGE_IMAGE, 1 << 20, STORAGE_IMAGE_BINDING),
-                (vk::DescriptorType::SAMPLED_IMAGE, 1 << 20, SAMPLED_IMAGE_BINDING)
+            let binding_infos: [(vk::DescriptorType, u32, u32); 3] = [
+                (vk::DescriptorType::SAMPLER, desc.bindless_samplers, SAMPLER_BINDING),
+                (vk::DescriptorType::STORAGE_IMAGE, desc.bindless_images, STORAGE_IMAGE_BINDING),
+                (vk::DescriptorType::SAMPLED_IMAGE, desc.bindless_images, SAMPLED_IMAGE_BINDING)
             ];
 
             let mut pool_sizes = Vec::new();
             let mut bindings = Vec::new();
             let mut binding_flags = Vec::new();
 
-            for binding_info in BINDING_INFOS {
+            for binding_info in binding_infos {
                 pool_sizes.push(vk::DescriptorPoolSize::default()
                     .ty(binding_info.0)
                     .descriptor_count(binding_info.1)
@@ -265,30 +533,46 @@ impl CobraVulkan {
                 binding_flags.push(vk::DescriptorBindingFlags::UPDATE_AFTER_BIND | vk::DescriptorBindingFlags::PARTIALLY_BOUND);
             }
 
-            let bindless_pool = device.create_descriptor_pool(&vk::DescriptorPoolCreateInfo::default()
-                .flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
-                .max_sets(1)
-                .pool_sizes(&pool_sizes)
-            , None)?;
-            let bindless_set_layout = device.create_descriptor_set_layout(&vk::DescriptorSetLayoutCreateInfo::default()
-                .flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
-                .bindings(&bindings)
-                .push_next(&mut vk::DescriptorSetLayoutBindingFlagsCreateInfo::default()
-                    .binding_flags(&binding_flags)
-                )
-            , None)?;
-            let bindless_set = device.allocate_descriptor_sets(&vk::DescriptorSetAllocateInfo::default()
-                .descriptor_pool(bindless_pool)
-                .set_layouts(&[bindless_set_layout])
-            )?[0];
-            let bindless_pipeline_layout = device.create_pipeline_layout(&vk::PipelineLayoutCreateInfo::default()
-                .set_layouts(&[bindless_set_layout])
-                .push_constant_ranges(&[vk::PushConstantRange::default()
-                    .stage_flags(vk::ShaderStageFlags::ALL)
-                    .offset(0)
-                    .size(128)
-                ])
-            , None)?;
+            let mut bindless_pool = vk::DescriptorPool::null();
+            let mut bindless_set_layout = vk::DescriptorSetLayout::null();
+            let created = (|| -> Result<(vk::DescriptorSet, vk::PipelineLayout)> {
+                bindless_pool = device.create_descriptor_pool(&vk::DescriptorPoolCreateInfo::default()
+                    .flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
+                    .max_sets(1)
+                    .pool_sizes(&pool_sizes)
+                , None)?;
+                bindless_set_layout = device.create_descriptor_set_layout(&vk::DescriptorSetLayoutCreateInfo::default()
+                    .flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
+                    .bindings(&bindings)
+                    .push_next(&mut vk::DescriptorSetLayoutBindingFlagsCreateInfo::default()
+                        .binding_flags(&binding_flags)
+                    )
+                , None)?;
+                let bindless_set = device.allocate_descriptor_sets(&vk::DescriptorSetAllocateInfo::default()
+                    .descriptor_pool(bindless_pool)
+                    .set_layouts(&[bindless_set_layout])
+                )?[0];
+                let bindless_pipeline_layout = device.create_pipeline_layout(&vk::PipelineLayoutCreateInfo::default()
+                    .set_layouts(&[bindless_set_layout])
+                    .push_constant_ranges(&[vk::PushConstantRange::default()
+                        .stage_flags(vk::ShaderStageFlags::ALL)
+                        .offset(0)
+                        .size(desc.push_constant_size)
+                    ])
+                , None)?;
+
+                Ok((bindless_set, bindless_pipeline_layout))
+            })();
+
+            let (bindless_set, bindless_pipeline_layout) = match created {
+                Ok(created) => created,
+                Err(err) => {
+                    // Destroying a null handle does nothing, so this only cleans up what was created before the error
+                    device.destroy_descriptor_set_layout(bindless_set_layout, None);
+                    device.destroy_descriptor_pool(bindless_pool, None);
+                    return Err(err);
+                }
+            };
 
             Ok((bindless_pool, bindless_set_layout, bindless_set, bindless_pipeline_layout))
         }
@@ -306,7 +590,14 @@ impl Drop for CobraVulkan {
             }
 
             self.graphics_queue.destroy();
-            self.push(self.staging_buffer.read().unwrap().as_ref().unwrap().allocation);
+            if let Some(queue) = &mut self.compute_queue { queue.destroy(); }
+            if let Some(queue) = &mut self.transfer_queue { queue.destroy(); }
+            if let Some(staging_buffer) = self.staging_buffer.read().unwrap().as_ref() {
+                self.push(staging_buffer.allocation);
+            }
+            for buffer in self.readback_pool.lock().unwrap().drain() {
+                self.push(buffer.allocation);
+            }
             self.flush();
 
             self.device.destroy_descriptor_set_layout(self.bindless_set_layout, None);
@@ -316,17 +607,79 @@ impl Drop for CobraVulkan {
             ManuallyDrop::drop(&mut self.allocator);
 
             self.device.destroy_device(None);
+            if let Some(debug_messenger) = &self.debug_messenger {
+                debug_messenger.destroy();
+            }
             self.instance.destroy_instance(None);
         }
     }
 }
 
+// Owns what new_with_desc created until the context takes over, destroying it if initialization fails on the way
+struct InitGuard {
+    instance: Option<ash::Instance>,
+    debug_messenger: Option<DebugMessenger>,
+    device: Option<ash::Device>,
+    bindless: Option<(vk::DescriptorPool, vk::DescriptorSetLayout, vk::PipelineLayout)>
+}
+
+impl InitGuard {
+    // Hands ownership to the context, the messenger is the only part that isn't a copyable handle
+    fn release(mut self) -> Option<DebugMessenger> {
+        self.instance = None;
+        self.device = None;
+        self.bindless = None;
+        self.debug_messenger.take()
+    }
+}
+
+impl Drop for InitGuard {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(device) = &self.device {
+                if let Some((pool, set_layout, pipeline_layout)) = self.bindless {
+                    device.destroy_pipeline_layout(pipeline_layout, None);
+                    device.destroy_descriptor_set_layout(set_layout, None);
+                    device.destroy_descriptor_pool(pool, None);
+                }
+                device.destroy_device(None);
+            }
+            if let Some(debug_messenger) = &self.debug_messenger {
+                debug_messenger.destroy();
+            }
+            if let Some(instance) = &self.instance {
+                instance.destroy_instance(None);
+            }
+        }
+    }
+}
+
+fn api_version(desc: &CobraDesc) -> u32 {
+    vk::make_api_version(0, desc.api_version.0, desc.api_version.1, 0)
+}
+
 #[cfg(target_os = "windows")]
-fn platform_surface_extensions() -> Vec<*const i8> {
-    vec![vk::KHR_WIN32_SURFACE_NAME.as_ptr()]
+fn platform_surface_extensions(_entry: &ash::Entry, display: Option<RawDisplayHandle>) -> Result<Vec<&'static CStr>> {
+    match display {
+        None | Some(RawDisplayHandle::Windows(_)) => Ok(vec![ash::khr::win32_surface::NAME]),
+        Some(display) => Err(CobraError::Unsupported(format!("Display handle {:?}", display)))
+    }
 }
 
 #[cfg(target_os = "linux")]
-fn platform_surface_extensions() -> Vec<*const i8> {
-    vec![vk::KHR_WAYLAND_SURFACE_NAME.as_ptr()]
-}
\ No newline at end of file
+fn platform_surface_extensions(entry: &ash::Entry, display: Option<RawDisplayHandle>) -> Result<Vec<&'static CStr>> {
+    match display {
+        Some(RawDisplayHandle::Xlib(_)) => Ok(vec![ash::khr::xlib_surface::NAME]),
+        Some(RawDisplayHandle::Xcb(_)) => Ok(vec![ash::khr::xcb_surface::NAME]),
+        Some(RawDisplayHandle::Wayland(_)) => Ok(vec![ash::khr::wayland_surface::NAME]),
+        Some(display) => Err(CobraError::Unsupported(format!("Display handle {:?}", display))),
+        None => unsafe {
+            // No display was given up front, so enable whichever window systems the loader supports
+            let available_extensions = entry.enumerate_instance_extension_properties(None)?;
+            Ok([ash::khr::xlib_surface::NAME, ash::khr::xcb_surface::NAME, ash::khr::wayland_surface::NAME]
+                .into_iter()
+                .filter(|name| available_extensions.iter().any(|extension| extension.extension_name_as_c_str() == Ok(*name)))
+                .collect())
+        }
+    }
+}
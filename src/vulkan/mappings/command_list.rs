@@ -1,23 +1,32 @@
-use anyhow::Result;
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::sync::atomic::Ordering;
+
 use ash::vk::{self, Rect2D};
-use glam::{IVec2, UVec2};
+use glam::{IVec2, UVec2, Vec4};
 
-use crate::{vulkan::internal_managers::{pipeline_manager::GraphicsPipelineKey, utils}, BlendFactor, BlendOp, ClearValue, CompareOperation, ICommandList, ISwapchain, IndexType, PipelineStage, Vulkan};
+use crate::{vulkan::internal_managers::{pipeline_manager::GraphicsPipelineKey, utils}, BlendFactor, BlendOp, ClearValue, CobraError, CompareOperation, ICommandList, ISwapchain, IndexType, PipelineStage, QueueType, Result, Vulkan};
 
-use super::{image::ImageVulkan, swapchain::SwapchainVulkan, BufferVulkan, CobraVulkan};
+use super::{image::ImageVulkan, queue::QueueVulkan, swapchain::SwapchainVulkan, BufferVulkan, CobraVulkan};
 
 pub struct CommandAllocator {
     pub(crate) command_pool: vk::CommandPool,
-    pub(crate) available_command_lists: Vec<CommandListVulkan>,
+    pub(crate) available_command_lists: Vec<vk::CommandBuffer>,
 }
 
 pub struct CommandListVulkan {
     pub(crate) command_buffer: vk::CommandBuffer,
     pub(crate) allocator: *mut CommandAllocator,
+    pub(crate) queue_family: u32,
 
     pub(crate) graphics_key: GraphicsPipelineKey,
     pub(crate) graphics_state_changed: bool,
 
+    labels: RefCell<Vec<bool>>, // whether each open label was recorded, so end_label matches a skipped begin_label
+    pub(crate) submit_value: Option<u64>, // set by the queue once the list was submitted
+    open_id: u64, // registration with the deletion queue, closed when the list is dropped
+
+    pub(crate) queue: *const QueueVulkan,
     pub(crate) cobra: *const CobraVulkan
 }
 
@@ -115,7 +124,7 @@ impl ICommandList<Vulkan> for CommandListVulkan {
             cobra.device.cmd_copy_image_to_buffer(self.command_buffer, src.allocation.0, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, dst.allocation.0, &[vk::BufferImageCopy::default()
                 .buffer_offset(dst_offset)
                 .image_subresource(vk::ImageSubresourceLayers::default()
-                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .aspect_mask(utils::image_format_aspect(src.format))
                     .layer_count(1)
                 )
                 .image_extent(vk::Extent3D { width: src.size.x, height: src.size.y, depth: 1 })
@@ -201,9 +210,9 @@ impl ICommandList<Vulkan> for CommandListVulkan {
             let cobra = &*self.cobra;
             cobra.device.cmd_pipeline_barrier2(self.command_buffer, &vk::DependencyInfo::default()
                 .memory_barriers(&[vk::MemoryBarrier2::default()
-                    .src_stage_mask(pipeline_stage_to_vulkan(src))
+                    .src_stage_mask(utils::pipeline_stage_to_vulkan(src))
                     .src_access_mask(vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE)
-                    .dst_stage_mask(pipeline_stage_to_vulkan(dst))
+                    .dst_stage_mask(utils::pipeline_stage_to_vulkan(dst))
                     .dst_access_mask(vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE)
                 ])
             );
@@ -215,9 +224,9 @@ impl ICommandList<Vulkan> for CommandListVulkan {
             let cobra = &*self.cobra;
             cobra.device.cmd_pipeline_barrier2(self.command_buffer, &vk::DependencyInfo::default()
                 .buffer_memory_barriers(&[vk::BufferMemoryBarrier2::default()
-                    .src_stage_mask(pipeline_stage_to_vulkan(src))
+                    .src_stage_mask(utils::pipeline_stage_to_vulkan(src))
                     .src_access_mask(vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE)
-                    .dst_stage_mask(pipeline_stage_to_vulkan(dst))
+                    .dst_stage_mask(utils::pipeline_stage_to_vulkan(dst))
                     .dst_access_mask(vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE)
                     .buffer(buffer.allocation.0)
                     .size(vk::WHOLE_SIZE)
@@ -226,10 +235,38 @@ impl ICommandList<Vulkan> for CommandListVulkan {
         }
     }
 
+    fn release_buffer(&self, buffer: &BufferVulkan, dst: QueueType) {
+        let dst_family = unsafe { (*self.cobra).queue_family(dst) };
+        if dst_family == self.queue_family { return; }
+
+        self.buffer_ownership_barrier(buffer, self.queue_family, dst_family, true);
+    }
+
+    fn acquire_buffer(&self, buffer: &BufferVulkan, src: QueueType) {
+        let src_family = unsafe { (*self.cobra).queue_family(src) };
+        if src_family == self.queue_family { return; }
+
+        self.buffer_ownership_barrier(buffer, src_family, self.queue_family, false);
+    }
+
+    fn release_image(&self, image: &ImageVulkan, dst: QueueType) {
+        let dst_family = unsafe { (*self.cobra).queue_family(dst) };
+        if dst_family == self.queue_family { return; }
+
+        self.image_ownership_barrier(image, self.queue_family, dst_family, true);
+    }
+
+    fn acquire_image(&self, image: &ImageVulkan, src: QueueType) {
+        let src_family = unsafe { (*self.cobra).queue_family(src) };
+        if src_family == self.queue_family { return; }
+
+        self.image_ownership_barrier(image, src_family, self.queue_family, false);
+    }
+
     fn bind_shaders(&mut self, shaders: &[&'static [u8]]) {
         self.graphics_state_changed = true;
-        for i in 0..shaders.len() {
-            self.graphics_key.shaders[i] = Some(shaders[i]);
+        for (i, shader) in shaders.iter().enumerate() {
+            self.graphics_key.shaders[i] = Some(*shader);
         }
     }
 
@@ -243,13 +280,19 @@ impl ICommandList<Vulkan> for CommandListVulkan {
         }
     }
 
-    fn push_constant<T>(&self, value: &T) {
+    fn push_constant<T>(&self, value: &T) -> Result<()> {
         unsafe {
             let cobra = &*self.cobra;
+            if core::mem::size_of::<T>() as u32 > cobra.push_constant_size {
+                return Err(CobraError::InvalidUsage(format!("Push constant of {} bytes is larger than the configured push_constant_size of {}", core::mem::size_of::<T>(), cobra.push_constant_size)));
+            }
+
             cobra.device.cmd_push_constants(self.command_buffer, cobra.bindless_pipeline_layout, vk::ShaderStageFlags::ALL, 0, core::slice::from_raw_parts(
                 (value as *const T) as *const u8, 
                 core::mem::size_of::<T>()
             ));
+
+            Ok(())
         }
     }
 
@@ -385,13 +428,90 @@ impl ICommandList<Vulkan> for CommandListVulkan {
             cobra.device.cmd_dispatch_indirect(self.command_buffer, buffer.allocation.0, offset);
         }
     }
+
+    fn begin_label(&self, name: &str, color: Vec4) {
+        unsafe {
+            let cobra = &*self.cobra;
+            // Only missing when neither the driver nor a capture layer exposes debug utils, then nothing could show the label anyway
+            let Some(debug_utils_fn) = &cobra.debug_utils_fn else { return; };
+
+            // Names with interior nul bytes can't be passed to Vulkan, the label is skipped like unnamed objects are
+            let name = CString::new(name).ok();
+            self.labels.borrow_mut().push(name.is_some());
+            let Some(name) = name else { return; };
+
+            debug_utils_fn.cmd_begin_debug_utils_label(self.command_buffer, &vk::DebugUtilsLabelEXT::default()
+                .label_name(&name)
+                .color(color.to_array())
+            );
+        }
+    }
+
+    fn end_label(&self) {
+        unsafe {
+            let cobra = &*self.cobra;
+            let Some(debug_utils_fn) = &cobra.debug_utils_fn else { return; };
+            if self.labels.borrow_mut().pop() == Some(false) { return; }
+
+            debug_utils_fn.cmd_end_debug_utils_label(self.command_buffer);
+        }
+    }
+
+    fn insert_label(&self, name: &str, color: Vec4) {
+        unsafe {
+            let cobra = &*self.cobra;
+            let Some(debug_utils_fn) = &cobra.debug_utils_fn else { return; };
+            let Ok(name) = CString::new(name) else { return; };
+
+            debug_utils_fn.cmd_insert_debug_utils_label(self.command_buffer, &vk::DebugUtilsLabelEXT::default()
+                .label_name(&name)
+                .color(color.to_array())
+            );
+        }
+    }
 }
 
 impl CommandListVulkan {
-    pub(crate) fn new(cobra: *const CobraVulkan, command_buffer: vk::CommandBuffer, allocator: *mut CommandAllocator) -> CommandListVulkan {
+    pub(crate) fn new(cobra: *const CobraVulkan, queue: *const QueueVulkan, command_buffer: vk::CommandBuffer, allocator: *mut CommandAllocator, queue_family: u32) -> CommandListVulkan {
         CommandListVulkan {
-            cobra, command_buffer, allocator,
-            graphics_key: GraphicsPipelineKey::new(), graphics_state_changed: false
+            cobra, queue, command_buffer, allocator, queue_family,
+            graphics_key: GraphicsPipelineKey::new(), graphics_state_changed: false,
+            labels: RefCell::new(Vec::new()),
+            submit_value: None,
+            open_id: unsafe { &*cobra }.open_command_list()
+        }
+    }
+
+    // Copies tightly packed rows starting at first_row, used to split image uploads across staging chunks
+    pub(crate) fn copy_buffer_to_image_rows(&self, src: &BufferVulkan, dst: &ImageVulkan, src_offset: u64, first_row: u32, row_count: u32) {
+        unsafe {
+            let cobra = &*self.cobra;
+            dst.transition_layout(self.command_buffer, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+
+            cobra.device.cmd_copy_buffer_to_image(self.command_buffer, src.allocation.0, dst.allocation.0, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &[vk::BufferImageCopy::default()
+                .buffer_offset(src_offset)
+                .image_subresource(vk::ImageSubresourceLayers::default()
+                    .aspect_mask(utils::image_format_aspect(dst.format))
+                    .layer_count(1)
+                )
+                .image_offset(vk::Offset3D { x: 0, y: first_row as i32, z: 0 })
+                .image_extent(vk::Extent3D { width: dst.size.x, height: row_count, depth: 1 })
+            ]);
+        }
+    }
+
+    // Makes transfer writes visible to the host once the submission's sync point completes
+    pub(crate) fn host_read_barrier(&self) {
+        unsafe {
+            let cobra = &*self.cobra;
+            cobra.device.cmd_pipeline_barrier2(self.command_buffer, &vk::DependencyInfo::default()
+                .memory_barriers(&[vk::MemoryBarrier2::default()
+                    .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+                    .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                    .dst_stage_mask(vk::PipelineStageFlags2::HOST)
+                    .dst_access_mask(vk::AccessFlags2::HOST_READ)
+                ])
+            );
         }
     }
 
@@ -402,18 +522,70 @@ impl CommandListVulkan {
         cobra.bind_graphics_pipeline(self.command_buffer, self.graphics_key)?;
         Ok(())
     }
-}
 
-fn pipeline_stage_to_vulkan(stages: PipelineStage) -> vk::PipelineStageFlags2 {
-    let mut ret = vk::PipelineStageFlags2::empty();
-    for stage in stages {
-        ret |= match stage {
-            PipelineStage::Compute => vk::PipelineStageFlags2::COMPUTE_SHADER,
-            PipelineStage::Transfer => vk::PipelineStageFlags2::ALL_TRANSFER,
-            PipelineStage::Graphics => vk::PipelineStageFlags2::ALL_GRAPHICS,
-            _ => unreachable!()
-        };
+    // The release half only needs the source scope and the acquire half only the destination scope, the other side is ignored by the driver
+    fn ownership_masks(release: bool) -> (vk::PipelineStageFlags2, vk::AccessFlags2, vk::PipelineStageFlags2, vk::AccessFlags2) {
+        match release {
+            true => (vk::PipelineStageFlags2::ALL_COMMANDS, vk::AccessFlags2::MEMORY_WRITE, vk::PipelineStageFlags2::NONE, vk::AccessFlags2::NONE),
+            false => (vk::PipelineStageFlags2::NONE, vk::AccessFlags2::NONE, vk::PipelineStageFlags2::ALL_COMMANDS, vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE)
+        }
+    }
+
+    fn buffer_ownership_barrier(&self, buffer: &BufferVulkan, src_family: u32, dst_family: u32, release: bool) {
+        unsafe {
+            let cobra = &*self.cobra;
+            let (src_stage, src_access, dst_stage, dst_access) = Self::ownership_masks(release);
+
+            cobra.device.cmd_pipeline_barrier2(self.command_buffer, &vk::DependencyInfo::default()
+                .buffer_memory_barriers(&[vk::BufferMemoryBarrier2::default()
+                    .src_stage_mask(src_stage)
+                    .src_access_mask(src_access)
+                    .dst_stage_mask(dst_stage)
+                    .dst_access_mask(dst_access)
+                    .src_queue_family_index(src_family)
+                    .dst_queue_family_index(dst_family)
+                    .buffer(buffer.allocation.0)
+                    .size(vk::WHOLE_SIZE)
+                ])
+            );
+        }
     }
 
-    ret
-}
\ No newline at end of file
+    fn image_ownership_barrier(&self, image: &ImageVulkan, src_family: u32, dst_family: u32, release: bool) {
+        unsafe {
+            let cobra = &*self.cobra;
+            let (src_stage, src_access, dst_stage, dst_access) = Self::ownership_masks(release);
+
+            // The layout is kept as is, both halves have to agree on it
+            let layout = vk::ImageLayout::from_raw(image.layout.load(Ordering::SeqCst));
+            cobra.device.cmd_pipeline_barrier2(self.command_buffer, &vk::DependencyInfo::default()
+                .image_memory_barriers(&[vk::ImageMemoryBarrier2::default()
+                    .src_stage_mask(src_stage)
+                    .src_access_mask(src_access)
+                    .dst_stage_mask(dst_stage)
+                    .dst_access_mask(dst_access)
+                    .src_queue_family_index(src_family)
+                    .dst_queue_family_index(dst_family)
+                    .old_layout(layout)
+                    .new_layout(layout)
+                    .image(image.allocation.0)
+                    .subresource_range(vk::ImageSubresourceRange::default()
+                        .aspect_mask(utils::image_format_aspect(image.format))
+                        .level_count(1)
+                        .layer_count(1)
+                    )
+                ])
+            );
+        }
+    }
+}
+
+impl Drop for CommandListVulkan {
+    fn drop(&mut self) {
+        unsafe {
+            (*self.queue).recycle_command_list(self.command_buffer, self.allocator, self.submit_value);
+            // A submitted list only gets here after the queue marked its value pending, so the deletions it held back wait for it
+            (*self.cobra).close_command_list(self.open_id);
+        }
+    }
+}
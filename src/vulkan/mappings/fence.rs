@@ -1,52 +1,92 @@
 use std::sync::atomic::{AtomicU64, Ordering};
-use anyhow::Result;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
 use ash::vk;
 
-use crate::{IFence, Vulkan};
+use crate::{CobraError, IFence, Result, Vulkan};
 
 use super::CobraVulkan;
 
 pub struct FenceVulkan {
     pub(crate) timeline_semaphore: vk::Semaphore,
+    timeline_value: AtomicU64,
     last_seen_value: AtomicU64,
 
-    cobra: *const CobraVulkan
+    // Weak since queues own their fence, once the context is gone the device has been idled so every value has been reached
+    cobra: Weak<CobraVulkan>,
+    // User created fences keep the context alive like any other resource
+    owner: Option<Arc<CobraVulkan>>
 }
 
 impl IFence<Vulkan> for FenceVulkan {
     fn wait(&self, value: Option<u64>) -> Result<()> {
-        let last_seen = self.last_seen_value.load(Ordering::SeqCst);
         let value = match value {
             Some(v) => v,
-            None => last_seen
+            None => self.pending_value()
         };
-        if last_seen >= value { return Ok(()); }
+
+        self.wait_timeout(value, Duration::MAX)?;
+        Ok(())
+    }
+
+    fn wait_timeout(&self, value: u64, timeout: Duration) -> Result<bool> {
+        if self.last_seen_value.load(Ordering::SeqCst) >= value { return Ok(true); }
+        let Some(cobra) = self.cobra.upgrade() else { return Ok(true); };
 
         unsafe {
-            (*self.cobra).device.wait_semaphores(&vk::SemaphoreWaitInfo::default()
+            let result = cobra.device.wait_semaphores(&vk::SemaphoreWaitInfo::default()
                 .semaphores(&[self.timeline_semaphore])
                 .values(&[value])
-            , u64::MAX)?;
+            , u64::try_from(timeout.as_nanos()).unwrap_or(u64::MAX));
+
+            match result {
+                Ok(()) => (),
+                Err(vk::Result::TIMEOUT) => return Ok(false),
+                Err(err) => return Err(err.into())
+            }
         }
 
-        self.last_seen_value.store(value, Ordering::SeqCst);
-        Ok(())
+        self.mark_reached(value);
+        Ok(true)
     }
 
-    fn pending_value(&self) -> u64 {
+    fn signal(&self, value: u64) -> Result<()> {
+        // Queue fences only ever move forward through their own submissions, a host signal would break the values handed out
+        if !self.is_user_fence() {
+            return Err(CobraError::InvalidUsage("Queue fences can't be signaled from the host".to_owned()));
+        }
+        let Some(cobra) = self.cobra.upgrade() else {
+            return Err(CobraError::InvalidUsage("Tried to signal a fence whose context was destroyed".to_owned()));
+        };
+
+        // Claims the value up front so two signals racing for it can't both get through, and keeps it above anything already submitted
+        if let Err(pending) = self.timeline_value.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pending| (value > pending).then_some(value)) {
+            return Err(CobraError::InvalidUsage(format!("Fences can only be signaled with increasing values, got {} but the fence is already at {}", value, pending)));
+        }
+
         unsafe {
-            (*self.cobra).timeline_value.load(Ordering::SeqCst)
+            cobra.device.signal_semaphore(&vk::SemaphoreSignalInfo::default()
+                .semaphore(self.timeline_semaphore)
+                .value(value)
+            )?;
         }
+
+        self.mark_reached(value);
+        Ok(())
+    }
+
+    fn pending_value(&self) -> u64 {
+        self.timeline_value.load(Ordering::SeqCst)
     }
 
     fn current_value(&self) -> Result<u64> {
-        let cobra = unsafe { &*self.cobra };
         let last_seen = self.last_seen_value.load(Ordering::SeqCst);
-        if last_seen >= cobra.timeline_value.load(Ordering::SeqCst) { return Ok(last_seen); }
+        if last_seen >= self.timeline_value.load(Ordering::SeqCst) { return Ok(last_seen); }
+        let Some(cobra) = self.cobra.upgrade() else { return Ok(self.pending_value()); };
 
         unsafe {
             let last_seen = cobra.device.get_semaphore_counter_value(self.timeline_semaphore)?;
-            self.last_seen_value.store(last_seen, Ordering::SeqCst);
+            self.mark_reached(last_seen);
             Ok(last_seen)
         }
     }
@@ -56,22 +96,54 @@ impl FenceVulkan {
     pub(crate) fn new() -> FenceVulkan {
         FenceVulkan {
             timeline_semaphore: vk::Semaphore::null(),
+            timeline_value: AtomicU64::new(0),
             last_seen_value: AtomicU64::new(0),
-            cobra: std::ptr::null()
+            cobra: Weak::new(),
+            owner: None
         }
     }
 
-    pub(crate) fn init(&mut self, cobra: *const CobraVulkan) -> Result<()> {
+    pub(crate) fn new_user(cobra: Arc<CobraVulkan>) -> Result<FenceVulkan> {
+        let mut fence = FenceVulkan::new();
+        fence.init(&Arc::downgrade(&cobra))?;
+        fence.owner = Some(cobra);
+
+        Ok(fence)
+    }
+
+    pub(crate) fn init(&mut self, cobra: &Weak<CobraVulkan>) -> Result<()> {
         unsafe {
-            self.timeline_semaphore = (*cobra).device.create_semaphore(&vk::SemaphoreCreateInfo::default()
+            self.cobra = cobra.clone();
+            self.timeline_semaphore = cobra.upgrade().unwrap().device.create_semaphore(&vk::SemaphoreCreateInfo::default()
                 .push_next(&mut vk::SemaphoreTypeCreateInfo::default()
                     .semaphore_type(vk::SemaphoreType::TIMELINE)
                     .initial_value(0)
                 )
             , None)?;
-            self.cobra = cobra;
 
             Ok(())
         }
     }
-}
\ No newline at end of file
+
+    pub(crate) fn is_user_fence(&self) -> bool {
+        self.owner.is_some()
+    }
+
+    // Called once a signal of the value was submitted, queues hold their submit lock while doing so
+    pub(crate) fn mark_pending(&self, value: u64) {
+        self.timeline_value.fetch_max(value, Ordering::SeqCst);
+    }
+
+    pub(crate) fn mark_reached(&self, value: u64) {
+        self.last_seen_value.fetch_max(value, Ordering::SeqCst);
+    }
+}
+
+impl Drop for FenceVulkan {
+    fn drop(&mut self) {
+        // Queue fences outlive the context and have their semaphore destroyed with the queue instead
+        if let Some(cobra) = self.cobra.upgrade() {
+            cobra.push(self.timeline_semaphore);
+        }
+    }
+}
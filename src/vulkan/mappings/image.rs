@@ -1,12 +1,11 @@
-use anyhow::{Error, Result};
 use ash::vk;
 use glam::UVec2;
 use vk_mem::Alloc;
 use std::sync::{atomic::{AtomicI32, Ordering}, Arc};
 
-use crate::{vulkan::internal_managers::{resource_handle::{ResourceHandle, ResourceType}, utils}, IBuffer, ICommandList, IImage, IQueue, ImageFormat, ImageUsage, Vulkan};
+use crate::{vulkan::internal_managers::{memory_report::ResourceMemoryType, resource_handle::{ResourceHandle, ResourceType}, utils}, CobraError, IImage, IQueue, IUploadBatch, ImageFormat, ImageUsage, Result, SyncPoint, Vulkan};
 
-use super::{cobra::{SAMPLED_IMAGE_BINDING, STORAGE_IMAGE_BINDING}, CobraVulkan};
+use super::{cobra::{SAMPLED_IMAGE_BINDING, STORAGE_IMAGE_BINDING}, CobraVulkan, QueueVulkan};
 
 pub struct ImageVulkan {
    pub(crate) allocation: (vk::Image, Option<vk_mem::Allocation>),
@@ -14,36 +13,40 @@ pub struct ImageVulkan {
    pub(crate) layout: AtomicI32,
    pub(crate) format: ImageFormat,
    pub(crate) size: UVec2,
+   allocated_size: u64,
    handle: Option<ResourceHandle>,
+   name: Option<String>,
 
    cobra: Arc<CobraVulkan>
 }
 
 impl IImage<Vulkan> for ImageVulkan {
-   fn set(&mut self, data: &[u8]) -> Result<()> {
-      let staging_buffer = self.cobra.staging_buffer.read().unwrap();
-      staging_buffer.as_ref().unwrap().host_slice()[0..data.len()].copy_from_slice(data);
-      
-      // TODO: use staging queue if available
-      let cmd = self.cobra.graphics_queue.begin()?;
-      cmd.copy_buffer_to_image(
-         staging_buffer.as_ref().unwrap(),
-         self, 0);
-      self.cobra.graphics_queue.submit(cmd, None)?.wait()?;
-
-      Ok(())
+   fn set(&mut self, queue: &QueueVulkan, data: &[u8]) -> Result<SyncPoint<Vulkan>> {
+      let mut upload = queue.begin_upload()?;
+      upload.write_image(self, data)?;
+      upload.submit()
    }
 
    fn handle(&self) -> Result<u32> {
       match &self.handle {
          Some(handle) => Ok(handle.id),
-         None => Err(Error::msg("Tried to get handle from an image with without Storage or Sampled usage"))
+         None => Err(CobraError::InvalidUsage("Tried to get handle from an image with without Storage or Sampled usage".to_owned()))
       }
    }
 
    fn size(&self) -> UVec2 {
        self.size
    }
+
+   fn set_name(&mut self, name: &str) {
+      self.cobra.set_object_name(self.allocation.0, name);
+      self.cobra.set_object_name(self.view, &format!("{} (view)", name));
+      self.name = Some(name.to_owned());
+   }
+
+   fn name(&self) -> Option<&str> {
+      self.name.as_deref()
+   }
 }
 
 impl ImageVulkan {
@@ -52,9 +55,11 @@ impl ImageVulkan {
          let size: UVec2 = size.into();
 
          let vulkan_format = utils::image_format_to_vulkan(format);
-         let mut allocation_info = vk_mem::AllocationCreateInfo::default();
-         allocation_info.usage = vk_mem::MemoryUsage::AutoPreferDevice;
-         allocation_info.required_flags = vk::MemoryPropertyFlags::DEVICE_LOCAL;
+         let allocation_info = vk_mem::AllocationCreateInfo {
+            usage: vk_mem::MemoryUsage::AutoPreferDevice,
+            required_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            ..Default::default()
+         };
          
          let allocation = cobra.allocator.create_image(&vk::ImageCreateInfo::default()
             .image_type(vk::ImageType::TYPE_2D)
@@ -69,6 +74,8 @@ impl ImageVulkan {
             .tiling(vk::ImageTiling::OPTIMAL)
             .usage(utils::image_usage_to_vulkan(usage))
          , &allocation_info)?;
+         let allocated_size = cobra.allocator.get_allocation_info(&allocation.1).size;
+         cobra.track_allocation(ResourceMemoryType::Image, allocated_size);
          let allocation = (allocation.0, Some(allocation.1));
 
          let view = cobra.device.create_image_view(&vk::ImageViewCreateInfo::default()
@@ -121,7 +128,8 @@ impl ImageVulkan {
 
          // return
          Ok(ImageVulkan {
-            allocation, view, format, handle, size, cobra,
+            allocation, view, format, handle, size, allocated_size, cobra,
+            name: None,
             layout: AtomicI32::new(vk::ImageLayout::UNDEFINED.as_raw())
          })
       }
@@ -131,8 +139,10 @@ impl ImageVulkan {
       ImageVulkan {
          view, format, size, cobra,
          allocation: (image, None),
+         allocated_size: 0,
          layout: AtomicI32::new(vk::ImageLayout::UNDEFINED.as_raw()),
-         handle: None
+         handle: None,
+         name: None
       }
    }
 
@@ -171,8 +181,9 @@ impl ImageVulkan {
 impl Drop for ImageVulkan {
    fn drop(&mut self) {
       self.cobra.push(self.view);
-      if self.allocation.1.is_some() {
-         self.cobra.push((self.allocation.0, self.allocation.1.unwrap()));
+      if let Some(allocation) = self.allocation.1.take() {
+         self.cobra.untrack_allocation(ResourceMemoryType::Image, self.allocated_size);
+         self.cobra.push((self.allocation.0, allocation));
       }
    }
 }
\ No newline at end of file
@@ -11,9 +11,13 @@ pub use sampler::SamplerVulkan;
 pub mod command_list;
 pub mod queue;
 pub mod fence;
+pub mod upload_batch;
+pub mod readback;
 pub use command_list::CommandListVulkan;
 pub use queue::QueueVulkan;
 pub use fence::FenceVulkan;
+pub use upload_batch::UploadBatchVulkan;
+pub use readback::ReadbackVulkan;
 
 pub mod swapchain;
 pub use swapchain::SwapchainVulkan;
\ No newline at end of file
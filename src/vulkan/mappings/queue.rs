@@ -1,26 +1,31 @@
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, Weak};
 use std::{collections::VecDeque, alloc::Layout, alloc::alloc, alloc::dealloc};
-use anyhow::Result;
 use ash::vk;
 
-use crate::{IFence, IQueue, SyncPoint, Vulkan};
+use crate::{vulkan::internal_managers::utils, CobraError, IBuffer, ICommandList, IFence, IQueue, PipelineStage, QueueType, Result, SubmitInfo, SyncPoint, Vulkan};
 
 use super::command_list::{CommandAllocator, CommandListVulkan};
 use super::fence::FenceVulkan;
+use super::readback::ReadbackVulkan;
 use super::swapchain::SwapchainVulkan;
-use super::CobraVulkan;
+use super::upload_batch::UploadBatchVulkan;
+use super::{BufferVulkan, CobraVulkan, ImageVulkan};
 
 const LAYOUT: Layout = Layout::new::<CommandAllocator>();
 
 pub struct QueueVulkan {
     queue: vk::Queue,
-    queue_family: u32,
-    fence: FenceVulkan,
+    pub(crate) ty: QueueType,
+    pub(crate) queue_family: u32,
+    pub(crate) fence: Arc<FenceVulkan>,
 
+    // Held from picking a signal value until it's submitted, so values reach the queue in order and are only marked pending once submitted
+    submit_lock: Mutex<()>,
     allocators: Mutex<VecDeque<*mut CommandAllocator>>,
-    pending_command_lists: Mutex<VecDeque<(CommandListVulkan, u64)>>,
+    pending_command_lists: Mutex<VecDeque<(vk::CommandBuffer, *mut CommandAllocator, u64)>>,
 
-    cobra: *const CobraVulkan
+    cobra: *const CobraVulkan,
+    cobra_ref: Weak<CobraVulkan> // for objects created by the queue that have to keep the context alive
 }
 unsafe impl Send for QueueVulkan { }
 unsafe impl Sync for QueueVulkan { }
@@ -31,7 +36,7 @@ impl IQueue<Vulkan> for QueueVulkan {
             let cobra = &*self.cobra;
 
             if swapchain.dirty { swapchain.recreate()? }
-            match cobra.swapchain_device_fn.acquire_next_image(swapchain.swapchain, u64::MAX, swapchain.semaphores[swapchain.semaphore_index], vk::Fence::null()) {
+            match cobra.wsi().swapchain_device_fn.acquire_next_image(swapchain.swapchain, u64::MAX, swapchain.semaphores[swapchain.semaphore_index], vk::Fence::null()) {
                 Ok((image_index, suboptimal)) => {
                     if suboptimal {
                         swapchain.dirty = true;
@@ -50,6 +55,8 @@ impl IQueue<Vulkan> for QueueVulkan {
                 }
             }
 
+            let submit = self.submit_lock.lock().unwrap();
+            let signal_value = self.fence.pending_value() + 1;
             cobra.device.queue_submit2(self.queue, &[vk::SubmitInfo2::default()
                 .wait_semaphore_infos(&[vk::SemaphoreSubmitInfo::default()
                     .semaphore(swapchain.semaphores[swapchain.semaphore_index])
@@ -57,29 +64,29 @@ impl IQueue<Vulkan> for QueueVulkan {
                 ])
                 .signal_semaphore_infos(&[vk::SemaphoreSubmitInfo::default()
                     .semaphore(self.fence.timeline_semaphore)
-                    .value(cobra.advance())
+                    .value(signal_value)
                     .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
                 ])
             ], vk::Fence::null())?;
+            self.fence.mark_pending(signal_value);
+            drop(submit);
 
             swapchain.semaphore_index = (swapchain.semaphore_index + 1) % swapchain.semaphores.len();
-            Ok(Some(SyncPoint::new_from_fence(&self.fence)))
+            Ok(Some(SyncPoint::new_from_fence(self.fence.clone(), signal_value)))
         }
     }
 
-    fn present(&self, swapchain: &mut SwapchainVulkan, wait: Option<&mut SyncPoint<Vulkan>>) -> Result<()> {
+    fn present(&self, swapchain: &mut SwapchainVulkan, wait: Option<&SyncPoint<Vulkan>>) -> Result<()> {
         unsafe {
             let cobra = &*self.cobra;
 
             let mut wait_info = Vec::new();
             if let Some(sync) = wait {
-                wait_info.push(vk::SemaphoreSubmitInfo::default()
-                    .semaphore((*(sync.fence.unwrap() as *const FenceVulkan)).timeline_semaphore)
-                    .value(sync.value())
-                    .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
-                );
+                push_wait_infos(&mut wait_info, sync, vk::PipelineStageFlags2::ALL_COMMANDS);
             }
 
+            // Presenting uses the queue as well, which has to be externally synchronized
+            let _submit = self.submit_lock.lock().unwrap();
             cobra.device.queue_submit2(self.queue, &[vk::SubmitInfo2::default()
                 .wait_semaphore_infos(&wait_info)
                 .signal_semaphore_infos(&[vk::SemaphoreSubmitInfo::default()
@@ -91,7 +98,7 @@ impl IQueue<Vulkan> for QueueVulkan {
             let binary_wait = swapchain.semaphores[swapchain.semaphore_index];
             swapchain.semaphore_index = (swapchain.semaphore_index + 1) % swapchain.semaphores.len();
 
-            match cobra.swapchain_device_fn.queue_present(self.queue, &vk::PresentInfoKHR::default()
+            match cobra.wsi().swapchain_device_fn.queue_present(self.queue, &vk::PresentInfoKHR::default()
                 .wait_semaphores(&[binary_wait])
                 .swapchains(&[swapchain.swapchain])
                 .image_indices(&[swapchain.image_index])
@@ -110,37 +117,68 @@ impl IQueue<Vulkan> for QueueVulkan {
         }
     }
 
-    fn submit(&self, cmd: CommandListVulkan, wait: Option<&mut SyncPoint<Vulkan>>) -> Result<SyncPoint<Vulkan>> {
+    fn submit(&self, cmd: CommandListVulkan, wait: Option<&SyncPoint<Vulkan>>) -> Result<SyncPoint<Vulkan>> {
+        let mut info = SubmitInfo::new().command_list(cmd);
+        if let Some(sync) = wait {
+            info = info.wait(sync, PipelineStage::All);
+        }
+
+        self.submit_batch(info)
+    }
+
+    fn submit_batch(&self, mut info: SubmitInfo<'_, Vulkan>) -> Result<SyncPoint<Vulkan>> {
         unsafe {
             let cobra = &*self.cobra;
-            cobra.device.end_command_buffer(cmd.command_buffer)?;
-            
+            // Any early return drops the command lists, which hands them back to their allocators
+            for cmd in info.command_lists.iter() {
+                cobra.device.end_command_buffer(cmd.command_buffer)?;
+            }
+
+            let command_buffer_info: Vec<vk::CommandBufferSubmitInfo> = info.command_lists.iter()
+                .map(|cmd| vk::CommandBufferSubmitInfo::default().command_buffer(cmd.command_buffer))
+                .collect();
+
             let mut wait_info = Vec::new();
-            if let Some(sync) = wait {
-                //sync.fence.unwrap() as *const Fence;
-                wait_info.push(vk::SemaphoreSubmitInfo::default()
-                    .semaphore((*(sync.fence.unwrap() as *const FenceVulkan)).timeline_semaphore)
-                    .value(sync.value())
+            for (sync, stages) in info.waits.iter() {
+                push_wait_infos(&mut wait_info, sync, utils::pipeline_stage_to_vulkan(*stages));
+            }
+
+            let _submit = self.submit_lock.lock().unwrap();
+            let signal_value = self.fence.pending_value() + 1;
+            let mut signal_info = vec![vk::SemaphoreSubmitInfo::default()
+                .semaphore(self.fence.timeline_semaphore)
+                .value(signal_value)
+                .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+            ];
+            for (fence, value) in info.signals.iter() {
+                if !fence.is_user_fence() {
+                    return Err(CobraError::InvalidUsage("Queue fences can only be signaled by their own queue".to_owned()));
+                }
+                if *value <= fence.pending_value() {
+                    return Err(CobraError::InvalidUsage(format!("Fences can only be signaled with increasing values, got {} but the fence is already at {}", value, fence.pending_value())));
+                }
+                signal_info.push(vk::SemaphoreSubmitInfo::default()
+                    .semaphore(fence.timeline_semaphore)
+                    .value(*value)
                     .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
                 );
             }
 
             cobra.device.queue_submit2(self.queue, &[vk::SubmitInfo2::default()
-                .command_buffer_infos(&[vk::CommandBufferSubmitInfo::default()
-                    .command_buffer(cmd.command_buffer)
-                ])
+                .command_buffer_infos(&command_buffer_info)
                 .wait_semaphore_infos(&wait_info)
-                .signal_semaphore_infos(&[vk::SemaphoreSubmitInfo::default()
-                    .semaphore(self.fence.timeline_semaphore)
-                    .value(cobra.advance())
-                    .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
-                ])
+                .signal_semaphore_infos(&signal_info)
             ], vk::Fence::null())?;
-            
-            self.allocators.lock().unwrap().push_back(cmd.allocator);
-            self.pending_command_lists.lock().unwrap().push_back((cmd, self.fence.pending_value()));
 
-            Ok(SyncPoint::new_from_fence(&self.fence))
+            self.fence.mark_pending(signal_value);
+            for (fence, value) in info.signals.iter() {
+                fence.mark_pending(*value);
+            }
+            for cmd in info.command_lists.iter_mut() {
+                cmd.submit_value = Some(signal_value);
+            }
+
+            Ok(SyncPoint::new_from_fence(self.fence.clone(), signal_value))
         }
     }
 
@@ -149,61 +187,95 @@ impl IQueue<Vulkan> for QueueVulkan {
             let cobra = &*self.cobra;
             let current_value = self.fence.current_value()?;
 
-            {
-                let mut pending_command_list = self.pending_command_lists.lock().unwrap();
-                while !pending_command_list.is_empty() {
-                    let pending = pending_command_list.front().unwrap();
-                    if current_value >= pending.1 {
-                        cobra.device.reset_command_buffer(pending.0.command_buffer, vk::CommandBufferResetFlags::empty())?;
-
-                        let pending = pending_command_list.pop_front().unwrap().0;
-                        let allocator = pending.allocator;
-                        (*allocator).available_command_lists.push(pending);
-                    } else {
-                        break;
-                    }
-                }
-            }
+            cobra.collect()?;
 
             let allocator = self.acquire_command_allocator()?;
-            let cmd = match (*allocator).available_command_lists.is_empty() {
+            // Only the lists of the allocator we hold can be recycled, the others might be in use by another thread
+            self.pending_command_lists.lock().unwrap().retain(|(command_buffer, owner, value)| match *owner == allocator && current_value >= *value {
                 true => {
-                    let cmd = cobra.device.allocate_command_buffers(&vk::CommandBufferAllocateInfo::default()
-                        .command_pool((*allocator).command_pool)
-                        .level(vk::CommandBufferLevel::PRIMARY)
-                        .command_buffer_count(1)
-                    )?[0];
-
-                    CommandListVulkan::new(self.cobra, cmd, allocator)
+                    (*allocator).available_command_lists.push(*command_buffer);
+                    false
+                }
+                false => true
+            });
+
+            let command_buffer = match (*allocator).available_command_lists.pop() {
+                Some(command_buffer) => command_buffer,
+                None => match cobra.device.allocate_command_buffers(&vk::CommandBufferAllocateInfo::default()
+                    .command_pool((*allocator).command_pool)
+                    .level(vk::CommandBufferLevel::PRIMARY)
+                    .command_buffer_count(1)
+                ) {
+                    Ok(command_buffers) => command_buffers[0],
+                    Err(err) => {
+                        self.allocators.lock().unwrap().push_back(allocator);
+                        return Err(err.into());
+                    }
                 }
-                false => (*allocator).available_command_lists.pop().unwrap()
             };
 
+            // From here on dropping the list on an error returns the allocator and command buffer
+            let cmd = CommandListVulkan::new(self.cobra, self, command_buffer, allocator, self.queue_family);
+
+            // The pool allows resetting single buffers, so beginning implicitly resets recycled ones
             cobra.device.begin_command_buffer(cmd.command_buffer, &vk::CommandBufferBeginInfo::default())?;
-            cobra.device.cmd_bind_descriptor_sets(cmd.command_buffer, vk::PipelineBindPoint::GRAPHICS, cobra.bindless_pipeline_layout, 0, &[cobra.bindless_set], &[]);
+            // Transfer queues can't bind descriptors at all, and only graphics queues have the graphics bind point
+            let bind_points: &[vk::PipelineBindPoint] = match self.ty {
+                QueueType::Graphics => &[vk::PipelineBindPoint::GRAPHICS, vk::PipelineBindPoint::COMPUTE],
+                QueueType::Compute => &[vk::PipelineBindPoint::COMPUTE],
+                QueueType::Transfer => &[]
+            };
+            for bind_point in bind_points {
+                cobra.device.cmd_bind_descriptor_sets(cmd.command_buffer, *bind_point, cobra.bindless_pipeline_layout, 0, &[cobra.bindless_set], &[]);
+            }
 
             Ok(cmd)
         }
     }
 
+    fn begin_upload(&self) -> Result<UploadBatchVulkan> {
+        Ok(UploadBatchVulkan::new(self, self.cobra))
+    }
+
+    fn readback_buffer(&self, buffer: &BufferVulkan, offset: u64, size: u64, wait: Option<&SyncPoint<Vulkan>>) -> Result<ReadbackVulkan> {
+        if size == 0 || offset + size > buffer.size() {
+            return Err(CobraError::InvalidUsage(format!("Tried to read back {} bytes at offset {} from a buffer of {} bytes", size, offset, buffer.size())));
+        }
+
+        self.readback(size, wait, |cmd, dst| cmd.copy_buffer_region(buffer, dst, size, offset, 0))
+    }
+
+    fn readback_image(&self, image: &mut ImageVulkan, wait: Option<&SyncPoint<Vulkan>>) -> Result<ReadbackVulkan> {
+        let size = image.size.x as u64 * image.size.y as u64 * image.format.texel_size();
+        if size == 0 {
+            return Err(CobraError::InvalidUsage("Tried to read back an empty image or one with an unknown format".to_owned()));
+        }
+
+        self.readback(size, wait, |cmd, dst| cmd.copy_image_to_buffer(image, dst, 0))
+    }
+
 }
 
 impl QueueVulkan {
-    pub(crate) fn new() -> QueueVulkan {
+    pub(crate) fn new(ty: QueueType) -> QueueVulkan {
         QueueVulkan {
             queue: vk::Queue::null(),
+            ty,
             queue_family: 0,
             cobra: std::ptr::null(),
-            fence: FenceVulkan::new(),
+            cobra_ref: Weak::new(),
+            fence: Arc::new(FenceVulkan::new()),
 
+            submit_lock: Mutex::new(()),
             allocators: Mutex::new(VecDeque::new()),
             pending_command_lists: Mutex::new(VecDeque::new())
         }
     }
 
-    pub(crate) fn init(&mut self, cobra: *const CobraVulkan, queue: vk::Queue, queue_family: u32) -> Result<()> {
-        self.fence.init(cobra)?;
-        self.cobra = cobra;
+    pub(crate) fn init(&mut self, cobra: &Arc<CobraVulkan>, queue: vk::Queue, queue_family: u32) -> Result<()> {
+        Arc::get_mut(&mut self.fence).unwrap().init(&Arc::downgrade(cobra))?;
+        self.cobra = Arc::as_ptr(cobra);
+        self.cobra_ref = Arc::downgrade(cobra);
         self.queue = queue;
         self.queue_family = queue_family;
 
@@ -211,6 +283,9 @@ impl QueueVulkan {
     }
 
     pub(crate) fn destroy(&mut self) {
+        // Initialization failed before the queue was set up, there's nothing to destroy
+        if self.cobra.is_null() { return; }
+
         unsafe {
             let cobra = &*self.cobra;
             for allocator in self.allocators.lock().unwrap().iter() {
@@ -224,6 +299,40 @@ impl QueueVulkan {
         }
     }
 
+    fn readback(&self, size: u64, wait: Option<&SyncPoint<Vulkan>>, record: impl FnOnce(&CommandListVulkan, &BufferVulkan)) -> Result<ReadbackVulkan> {
+        let cobra = self.cobra_ref.upgrade().unwrap();
+        let buffer = cobra.acquire_readback_buffer(&self.cobra_ref, size)?;
+
+        let submitted = (|| {
+            // Submission order alone doesn't order execution, so wait on everything submitted to this queue before
+            let cmd = self.begin()?;
+            cmd.barrier(PipelineStage::All, PipelineStage::Transfer);
+            record(&cmd, &buffer);
+            cmd.host_read_barrier();
+            self.submit(cmd, wait)
+        })();
+
+        match submitted {
+            Ok(sync) => Ok(ReadbackVulkan::new(cobra, buffer, size, sync)),
+            Err(err) => {
+                cobra.release_readback_buffer(buffer, SyncPoint::new());
+                Err(err)
+            }
+        }
+    }
+
+    // Called when a command list is dropped, submitted lists wait for their value before their buffer is reused
+    pub(crate) fn recycle_command_list(&self, command_buffer: vk::CommandBuffer, allocator: *mut CommandAllocator, submit_value: Option<u64>) {
+        unsafe {
+            match submit_value {
+                Some(value) => self.pending_command_lists.lock().unwrap().push_back((command_buffer, allocator, value)),
+                None => (*allocator).available_command_lists.push(command_buffer)
+            }
+
+            self.allocators.lock().unwrap().push_back(allocator);
+        }
+    }
+
     fn acquire_command_allocator(&self) -> Result<*mut CommandAllocator> {
         unsafe {
             let cobra = &*self.cobra;
@@ -246,4 +355,14 @@ impl QueueVulkan {
             })
         }
     }
+}
+
+fn push_wait_infos(wait_info: &mut Vec<vk::SemaphoreSubmitInfo<'_>>, sync: &SyncPoint<Vulkan>, stages: vk::PipelineStageFlags2) {
+    for (fence, value) in sync.points() {
+        wait_info.push(vk::SemaphoreSubmitInfo::default()
+            .semaphore(fence.timeline_semaphore)
+            .value(*value)
+            .stage_mask(stages)
+        );
+    }
 }
\ No newline at end of file
@@ -0,0 +1,57 @@
+use std::mem::ManuallyDrop;
+use std::sync::Arc;
+
+use crate::{IBuffer, IReadback, Result, SyncPoint, Vulkan};
+
+use super::{BufferVulkan, CobraVulkan};
+
+pub struct ReadbackVulkan {
+    buffer: Option<ManuallyDrop<BufferVulkan>>, // only None while being returned to the pool
+    size: u64,
+    sync: SyncPoint<Vulkan>,
+
+    cobra: Arc<CobraVulkan>
+}
+
+impl IReadback<Vulkan> for ReadbackVulkan {
+    fn sync(&self) -> &SyncPoint<Vulkan> {
+        &self.sync
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn try_read(&self) -> Result<Option<Vec<u8>>> {
+        if !self.sync.is_complete()? { return Ok(None); }
+        Ok(Some(self.bytes()?))
+    }
+
+    fn read(self) -> Result<Vec<u8>> {
+        self.sync.wait()?;
+        self.bytes()
+    }
+}
+
+impl ReadbackVulkan {
+    pub(crate) fn new(cobra: Arc<CobraVulkan>, buffer: ManuallyDrop<BufferVulkan>, size: u64, sync: SyncPoint<Vulkan>) -> ReadbackVulkan {
+        ReadbackVulkan {
+            buffer: Some(buffer),
+            size, sync, cobra
+        }
+    }
+
+    fn bytes(&self) -> Result<Vec<u8>> {
+        let data = self.buffer.as_ref().unwrap().map_read::<u8>()?;
+        Ok(data[..self.size as usize].to_vec())
+    }
+}
+
+impl Drop for ReadbackVulkan {
+    fn drop(&mut self) {
+        // Dropping before the copy finished is fine, the pool only hands the buffer out again once the sync point completes
+        if let Some(buffer) = self.buffer.take() {
+            self.cobra.release_readback_buffer(buffer, std::mem::take(&mut self.sync));
+        }
+    }
+}
@@ -1,14 +1,14 @@
 use std::sync::Arc;
-use anyhow::Result;
 use ash::{self, vk};
 
-use crate::{vulkan::internal_managers::resource_handle::{ResourceHandle, ResourceType}, ISampler, Vulkan};
+use crate::{vulkan::internal_managers::resource_handle::{ResourceHandle, ResourceType}, ISampler, Result, Vulkan};
 
 use super::{cobra::SAMPLER_BINDING, CobraVulkan};
 
 pub struct SamplerVulkan {
     sampler: vk::Sampler,
     handle: ResourceHandle,
+    name: Option<String>,
 
     cobra: Arc<CobraVulkan>
 }
@@ -17,6 +17,15 @@ impl ISampler<Vulkan> for SamplerVulkan {
     fn handle(&self) -> u32 {
         self.handle.id
     }
+
+    fn set_name(&mut self, name: &str) {
+        self.cobra.set_object_name(self.sampler, name);
+        self.name = Some(name.to_owned());
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
 }
 
 impl SamplerVulkan {
@@ -41,7 +50,8 @@ impl SamplerVulkan {
             ], &[]);
 
             Ok(SamplerVulkan {
-                sampler, handle, cobra
+                sampler, handle, cobra,
+                name: None
             })
         }
     }
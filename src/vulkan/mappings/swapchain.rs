@@ -1,11 +1,10 @@
 use std::sync::Arc;
 
-use anyhow::Result;
 use ash::vk::{self, ImageSubresourceRange};
 use glam::UVec2;
-use std::ffi::c_void;
+use raw_window_handle::{HasDisplayHandle, HasWindowHandle, RawDisplayHandle, RawWindowHandle};
 
-use crate::{ISwapchain, ImageFormat, Vulkan};
+use crate::{CobraError, IImage, ISwapchain, ImageFormat, Result, Vulkan};
 
 use super::{image::ImageVulkan, CobraVulkan};
 
@@ -22,6 +21,7 @@ pub struct SwapchainVulkan {
 
     size: UVec2,
     pub(crate) dirty: bool,
+    name: Option<String>,
 
     cobra: Arc<CobraVulkan>
 }
@@ -41,13 +41,22 @@ impl ISwapchain<Vulkan> for SwapchainVulkan {
     fn size(&self) -> UVec2 {
         self.size
     }
+
+    fn set_name(&mut self, name: &str) {
+        self.name = Some(name.to_owned());
+        self.apply_names();
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
 }
 
 impl SwapchainVulkan {
-    pub(crate) fn new(cobra: Arc<CobraVulkan>, window: *mut c_void, size: UVec2) -> Result<Self> {
+    pub(crate) fn new(cobra: Arc<CobraVulkan>, window: &(impl HasWindowHandle + HasDisplayHandle), size: UVec2) -> Result<Self> {
         unsafe {
-            let surface = create_surface(&cobra, window)?;
-            let surface_format = Self::choose_surface_format(&cobra.surface_fn.get_physical_device_surface_formats(cobra.chosen_gpu, surface)?);
+            let surface = create_surface(&cobra, window.window_handle()?.as_raw(), window.display_handle()?.as_raw())?;
+            let surface_format = Self::choose_surface_format(&cobra.wsi().surface_fn.get_physical_device_surface_formats(cobra.chosen_gpu, surface)?);
             let mut size = size;
             let (swapchain, images) = Self::create_swapchain(&cobra, surface, surface_format, None, &mut size)?;
 
@@ -60,18 +69,19 @@ impl SwapchainVulkan {
                 surface, surface_format, swapchain, images, semaphores, size, cobra,
                 image_index: 0,
                 semaphore_index: 0,
-                dirty: false
+                dirty: false,
+                name: None
             })
         }
     }
 
     fn create_swapchain(cobra: &Arc<CobraVulkan>, surface: vk::SurfaceKHR, surface_format: vk::SurfaceFormatKHR, old_swapchain: Option<vk::SwapchainKHR>, size: &mut UVec2) -> Result<(vk::SwapchainKHR, Vec<ImageVulkan>)> {
         unsafe {
-            let capabilities = cobra.surface_fn.get_physical_device_surface_capabilities(cobra.chosen_gpu, surface)?;
+            let capabilities = cobra.wsi().surface_fn.get_physical_device_surface_capabilities(cobra.chosen_gpu, surface)?;
             let extent = Self::choose_swap_extent(&capabilities, size);
             *size = UVec2::new(extent.width, extent.height);
 
-            let swapchain = cobra.swapchain_device_fn.create_swapchain(&vk::SwapchainCreateInfoKHR::default()
+            let swapchain = cobra.wsi().swapchain_device_fn.create_swapchain(&vk::SwapchainCreateInfoKHR::default()
                 .surface(surface)
                 .min_image_count(capabilities.min_image_count)
                 .image_format(surface_format.format)
@@ -90,12 +100,11 @@ impl SwapchainVulkan {
                 })
             , None)?;
 
-            // TODO: probably delay this a few frames by putting in the deletion queue instead
             if let Some(swapchain) = old_swapchain {
-                cobra.swapchain_device_fn.destroy_swapchain(swapchain, None);
+                cobra.push(swapchain);
             }
 
-            let vulkan_images = cobra.swapchain_device_fn.get_swapchain_images(swapchain)?;
+            let vulkan_images = cobra.wsi().swapchain_device_fn.get_swapchain_images(swapchain)?;
             let images: Vec<ImageVulkan> = vulkan_images
                 .iter()
                 .map(|image| {
@@ -110,11 +119,11 @@ impl SwapchainVulkan {
                             base_array_layer: 0,
                             layer_count: 1
                         })
-                    , None).unwrap();
+                    , None)?;
                     
-                    ImageVulkan::new_swapchain_image(cobra.clone(), *image, image_view, ImageFormat::R8G8B8A8Unorm, *size)
+                    Ok(ImageVulkan::new_swapchain_image(cobra.clone(), *image, image_view, ImageFormat::R8G8B8A8Unorm, *size))
                 })
-                .collect();
+                .collect::<Result<_>>()?;
 
             Ok((swapchain, images))
         }
@@ -148,9 +157,20 @@ impl SwapchainVulkan {
         self.swapchain = swapchain;
         self.images = images;
         self.dirty = false;
+        self.apply_names();
 
         Ok(())
     }
+
+    fn apply_names(&mut self) {
+        let Some(name) = self.name.clone() else { return; };
+
+        self.cobra.set_object_name(self.swapchain, &name);
+        self.cobra.set_object_name(self.surface, &format!("{} (surface)", name));
+        for (i, image) in self.images.iter_mut().enumerate() {
+            image.set_name(&format!("{} image {}", name, i));
+        }
+    }
 }
 
 impl Drop for SwapchainVulkan {
@@ -158,28 +178,65 @@ impl Drop for SwapchainVulkan {
         self.cobra.push(self.swapchain);
         self.cobra.push(self.surface);
 
-        for (_, semaphore) in self.semaphores.iter().enumerate() {
+        for semaphore in self.semaphores.iter() {
             self.cobra.push(*semaphore);
         }
     }
 }
 
 #[cfg(target_os = "windows")]
-fn create_surface(cobra: &Arc<CobraVulkan>, window: *mut c_void) -> Result<vk::SurfaceKHR> {
+fn create_surface(cobra: &Arc<CobraVulkan>, window: RawWindowHandle, display: RawDisplayHandle) -> Result<vk::SurfaceKHR> {
     unsafe {
-        Ok(cobra.platform_surface_fn.create_win32_surface(&ash::vk::Win32SurfaceCreateInfoKHR::default()
-            .hinstance(kernel32::GetModuleHandleA(std::ptr::null()) as vk::HINSTANCE)
-            .hwnd(window as vk::HWND)
-        , None)?)
+        let surface_fn = cobra.wsi().win32_surface_fn.as_ref().ok_or_else(|| CobraError::Unsupported("VK_KHR_win32_surface was not enabled on this context".to_owned()))?;
+
+        match (window, display) {
+            (RawWindowHandle::Win32(window), RawDisplayHandle::Windows(_)) => {
+                Ok(surface_fn.create_win32_surface(&ash::vk::Win32SurfaceCreateInfoKHR::default()
+                    .hinstance(match window.hinstance {
+                        Some(hinstance) => hinstance.get(),
+                        None => kernel32::GetModuleHandleA(std::ptr::null()) as vk::HINSTANCE
+                    })
+                    .hwnd(window.hwnd.get())
+                , None)?)
+            },
+            (window, display) => Err(CobraError::Unsupported(format!("Window handle {:?} for display {:?}", window, display)))
+        }
     }
 }
 
 #[cfg(target_os = "linux")]
-fn create_surface(cobra: &Arc<CobraVulkan>, window: *mut c_void) -> Result<vk::SurfaceKHR> {
+fn create_surface(cobra: &Arc<CobraVulkan>, window: RawWindowHandle, display: RawDisplayHandle) -> Result<vk::SurfaceKHR> {
     unsafe {
-        Ok(cobra.platform_surface_fn.create_wayland_surface(&ash::vk::WaylandSurfaceCreateInfoKHR::default()
-            //.display(kernel32::GetModuleHandleA(std::ptr::null()) as vk::HINSTANCE)
-            .surface(window as vk::wl_surface)
-        , None)?)
+        let wsi = cobra.wsi();
+
+        match (window, display) {
+            (RawWindowHandle::Xlib(window), RawDisplayHandle::Xlib(display)) => {
+                let surface_fn = wsi.xlib_surface_fn.as_ref().ok_or_else(|| CobraError::Unsupported("VK_KHR_xlib_surface was not enabled on this context".to_owned()))?;
+                let display = display.display.ok_or_else(|| CobraError::InvalidUsage("Xlib display handle has no display connection".to_owned()))?;
+
+                Ok(surface_fn.create_xlib_surface(&ash::vk::XlibSurfaceCreateInfoKHR::default()
+                    .dpy(display.as_ptr())
+                    .window(window.window)
+                , None)?)
+            },
+            (RawWindowHandle::Xcb(window), RawDisplayHandle::Xcb(display)) => {
+                let surface_fn = wsi.xcb_surface_fn.as_ref().ok_or_else(|| CobraError::Unsupported("VK_KHR_xcb_surface was not enabled on this context".to_owned()))?;
+                let connection = display.connection.ok_or_else(|| CobraError::InvalidUsage("XCB display handle has no connection".to_owned()))?;
+
+                Ok(surface_fn.create_xcb_surface(&ash::vk::XcbSurfaceCreateInfoKHR::default()
+                    .connection(connection.as_ptr())
+                    .window(window.window.get())
+                , None)?)
+            },
+            (RawWindowHandle::Wayland(window), RawDisplayHandle::Wayland(display)) => {
+                let surface_fn = wsi.wayland_surface_fn.as_ref().ok_or_else(|| CobraError::Unsupported("VK_KHR_wayland_surface was not enabled on this context".to_owned()))?;
+
+                Ok(surface_fn.create_wayland_surface(&ash::vk::WaylandSurfaceCreateInfoKHR::default()
+                    .display(display.display.as_ptr())
+                    .surface(window.surface.as_ptr())
+                , None)?)
+            },
+            (window, display) => Err(CobraError::Unsupported(format!("Window handle {:?} for display {:?}", window, display)))
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,142 @@
+use crate::{vulkan::internal_managers::staging_ring::STAGING_ALIGNMENT, CobraError, IBuffer, ICommandList, IQueue, IUploadBatch, Result, SyncPoint, Vulkan};
+
+use super::{BufferVulkan, CobraVulkan, CommandListVulkan, ImageVulkan, QueueVulkan};
+
+pub struct UploadBatchVulkan {
+    cmd: Option<CommandListVulkan>, // only begun once there's something to record
+    reservations: Vec<u64>,
+    sync: SyncPoint<Vulkan>,
+
+    queue: *const QueueVulkan,
+    cobra: *const CobraVulkan
+}
+
+impl IUploadBatch<Vulkan> for UploadBatchVulkan {
+    fn write_buffer<U>(&mut self, buffer: &BufferVulkan, source: &[U], buffer_offset: u64) -> Result<()>
+        where U: Copy {
+        let source = unsafe { std::slice::from_raw_parts(source.as_ptr().cast::<u8>(), std::mem::size_of_val(source)) };
+        if buffer_offset + source.len() as u64 > buffer.size() {
+            return Err(CobraError::InvalidUsage(format!("Tried to write {} bytes at offset {} into a buffer of {} bytes", source.len(), buffer_offset, buffer.size())));
+        }
+
+        let max_chunk = self.cobra().staging_ring.max_chunk() as usize;
+        for (i, chunk) in source.chunks(max_chunk).enumerate() {
+            let staging_offset = self.write_staging(chunk)?;
+
+            let cobra = self.cobra();
+            let staging_buffer = cobra.staging_buffer.read().unwrap();
+            self.cmd.as_ref().unwrap().copy_buffer_region(staging_buffer.as_ref().unwrap(), buffer, chunk.len() as u64, staging_offset, buffer_offset + (i * max_chunk) as u64);
+        }
+
+        Ok(())
+    }
+
+    fn write_image(&mut self, image: &ImageVulkan, data: &[u8]) -> Result<()> {
+        let row_size = (image.size.x as u64 * image.format.texel_size()) as usize;
+        if row_size == 0 {
+            return Err(CobraError::InvalidUsage("Tried to upload to an empty image or one with an unknown format".to_owned()));
+        }
+        if data.len() != row_size * image.size.y as usize {
+            return Err(CobraError::InvalidUsage(format!("Expected {} bytes of image data, got {}", row_size * image.size.y as usize, data.len())));
+        }
+
+        let max_chunk = self.cobra().staging_ring.max_chunk() as usize;
+        if row_size > max_chunk {
+            return Err(CobraError::InvalidUsage(format!("A single image row of {} bytes doesn't fit in a staging chunk of {} bytes, increase staging_buffer_size", row_size, max_chunk)));
+        }
+
+        // Chunks are whole rows so each one is a plain buffer to image copy
+        let rows_per_chunk = max_chunk / row_size;
+        for (i, chunk) in data.chunks(rows_per_chunk * row_size).enumerate() {
+            let staging_offset = self.write_staging(chunk)?;
+
+            let cobra = self.cobra();
+            let staging_buffer = cobra.staging_buffer.read().unwrap();
+            self.cmd.as_ref().unwrap().copy_buffer_to_image_rows(staging_buffer.as_ref().unwrap(), image, staging_offset, (i * rows_per_chunk) as u32, (chunk.len() / row_size) as u32);
+        }
+
+        Ok(())
+    }
+
+    fn submit(mut self) -> Result<SyncPoint<Vulkan>> {
+        self.flush()?;
+        Ok(std::mem::take(&mut self.sync))
+    }
+}
+
+impl UploadBatchVulkan {
+    pub(crate) fn new(queue: *const QueueVulkan, cobra: *const CobraVulkan) -> UploadBatchVulkan {
+        UploadBatchVulkan {
+            cmd: None,
+            reservations: Vec::new(),
+            sync: SyncPoint::new(),
+
+            queue, cobra
+        }
+    }
+
+    fn cobra(&self) -> &CobraVulkan {
+        unsafe { &*self.cobra }
+    }
+
+    // Copies the data into the staging ring and makes sure there's a command list to record the copy into
+    fn write_staging(&mut self, data: &[u8]) -> Result<u64> {
+        let size = (data.len() as u64).next_multiple_of(STAGING_ALIGNMENT);
+        let (id, offset) = loop {
+            if let Some(allocation) = self.cobra().staging_ring.try_allocate(size)? {
+                break allocation;
+            }
+
+            // Our own chunks might be what's filling the ring, they have to be submitted before waiting on it
+            self.flush()?;
+            self.cobra().staging_ring.wait_for_space()?;
+        };
+
+        if self.cmd.is_none() {
+            match unsafe { &*self.queue }.begin() {
+                Ok(cmd) => self.cmd = Some(cmd),
+                Err(err) => {
+                    // Nothing will ever read the region, so hand it straight back
+                    self.cobra().staging_ring.submit(&[id], &SyncPoint::new());
+                    return Err(err);
+                }
+            }
+        }
+        self.reservations.push(id);
+
+        unsafe {
+            let staging_buffer = self.cobra().staging_buffer.read().unwrap();
+            let dst = staging_buffer.as_ref().unwrap().host_address()?.cast::<u8>().add(offset as usize);
+            std::ptr::copy_nonoverlapping(data.as_ptr(), dst, data.len());
+        }
+
+        Ok(offset)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let Some(cmd) = self.cmd.take() else { return Ok(()); };
+
+        let sync = match unsafe { &*self.queue }.submit(cmd, None) {
+            Ok(sync) => sync,
+            Err(err) => {
+                // The copies will never run, so the regions can be handed straight back
+                self.cobra().staging_ring.submit(&self.reservations, &SyncPoint::new());
+                self.reservations.clear();
+                return Err(err);
+            }
+        };
+        self.cobra().staging_ring.submit(&self.reservations, &sync);
+        self.reservations.clear();
+        self.sync.merge(&sync);
+
+        Ok(())
+    }
+}
+
+impl Drop for UploadBatchVulkan {
+    // Unsubmitted writes are discarded, dropping the command list and handing the reservations back so they don't block the ring
+    fn drop(&mut self) {
+        self.cmd = None;
+        self.cobra().staging_ring.submit(&self.reservations, &SyncPoint::new());
+    }
+}